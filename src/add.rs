@@ -1,61 +1,140 @@
-use cluster::{Cluster, Error, Node, Role};
-use std::collections::HashMap;
+use cluster::{AsResult, Cluster, Error, Node, Role};
+use std::collections::{BTreeMap, HashMap};
+
+#[test]
+fn test_split_pair() {
+    assert_eq!(
+        split_pair("127.0.0.1:7000").unwrap(),
+        (None, "127.0.0.1:7000")
+    );
+    assert_eq!(
+        split_pair("127.0.0.1:7001,redis-1/127.0.0.1:7000,redis-0").unwrap(),
+        (Some("127.0.0.1:7001,redis-1"), "127.0.0.1:7000,redis-0")
+    );
+    assert!(split_pair("127.0.0.1:7001,127.0.0.1:7000").is_err());
+    assert!(split_pair("127.0.0.1:7001@zone=b,127.0.0.1:7000@zone=a").is_err());
+    assert!(split_pair("127.0.0.1:7002/127.0.0.1:7001/127.0.0.1:7000").is_err());
+}
+
 #[derive(Debug)]
 pub struct Add {
-    origin: String,
     pub cluster: Cluster,
     node: Node,
     slave_master: HashMap<String, String>,
 }
 
 impl Add {
-    pub fn new(origin: String, addrs: Vec<String>) -> Result<Add, Error> {
-        let mut node = Node::new(origin.as_bytes()).unwrap();
+    pub fn new(origin: String, addrs: Vec<String>, allow_same_zone: bool) -> Result<Add, Error> {
+        let mut node = Node::new(origin.as_bytes())?;
 
-        node.connect();
+        node.connect()?;
         let mut nodes = Vec::new();
         let mut sm = HashMap::new();
+        let mut zones = Vec::new();
         for n in addrs.into_iter() {
-            let mut ms: Vec<&str> = n.split(',').collect();
-            let master_host = ms.pop().unwrap();
-            let mut master = Node::new(master_host.as_bytes()).expect("create new node fail");
-            master.connect();
-            nodes.push(master);
-            if ms.len() == 1 {
-                let slave_host = ms.pop().unwrap();
-                let mut node = Node::new(slave_host.as_bytes()).expect("create new node fail");
+            let (slave_host, master_host) = split_pair(&n)?;
+            let mut master = Node::new(master_host.as_bytes())?;
+            master.connect()?;
+            let master_addr = master.addr();
+            let master_zone = master.zone();
+            zones.extend(master_zone.clone());
+            if let Some(slave_host) = slave_host {
+                let mut node = Node::new(slave_host.as_bytes())?;
                 node.set_role(Role::Slave);
+                if node.zone().is_some() && node.zone() == master_zone {
+                    let msg = format!(
+                        "replica {} is in zone {} of its master {}",
+                        node.addr(),
+                        master_zone.clone().unwrap_or_default(),
+                        master_addr
+                    );
+                    if !allow_same_zone {
+                        return Err(Error::State(format!("{}, pass --allow-same-zone", msg)));
+                    }
+                    eprintln!("warning: {}", msg);
+                }
+                sm.insert(node.addr(), master_addr);
+                nodes.push(master);
                 nodes.push(node);
-                sm.insert(slave_host.to_string(), master_host.to_string());
+            } else {
+                nodes.push(master);
             }
         }
+        for master in node.nodes()?.iter().filter(|x| x.is_master()) {
+            zones.extend(master.zone());
+        }
+        warn_uneven(&zones);
         Ok(Add {
-            origin,
             slave_master: sm,
             cluster: Cluster::new(nodes),
             node,
         })
     }
-    pub fn add_node(&self) -> Result<(), Error> {
+    pub fn add_node(&self) -> AsResult<()> {
         for node in &self.cluster.nodes {
-            self.node.meet(&*node.ip, &*node.port);
+            self.node.meet(node)?;
         }
         Ok(())
     }
-    pub fn set_slave(&mut self) {
+    pub fn set_slave(&mut self) -> AsResult<()> {
         let mut nodes_info = HashMap::new();
         for node in &self.cluster.nodes {
-            nodes_info.insert(node.ip.clone() + ":" + &*node.port, node.clone());
+            nodes_info.insert(node.addr(), node.clone());
         }
         println!("nodes_info {:?}", nodes_info);
         println!("s_m info {:?}", self.slave_master);
         for node in self.cluster.nodes.iter_mut() {
             if self.slave_master.contains_key(&*node.addr()) {
                 let master = &self.slave_master[&node.addr()];
-                let master_node = nodes_info.get(master);
-                node.slaveof = Some(master_node.unwrap().clone().name);
-                let _: () = node.set_slave();
+                let master_node = nodes_info.get(master).ok_or_else(|| {
+                    Error::State(format!("master {} of {} is not added", master, node.addr()))
+                })?;
+                node.slaveof = Some(master_node.name.clone());
+                node.set_slave()?;
             }
         }
+        Ok(())
+    }
+}
+
+// a node to add is `master` or `replica/master`, ',' separates the hostname of an address
+fn split_pair(arg: &str) -> AsResult<(Option<&str>, &str)> {
+    let (slave, master) = match arg.split_once('/') {
+        Some((slave, master)) => (Some(slave), master),
+        None => (None, arg),
+    };
+    if master.contains('/') {
+        return Err(Error::State(format!(
+            "bad node {}, a replica and its master are paired by a single '/'",
+            arg
+        )));
+    }
+    // a hostname has no port, an address after ',' is the replica,master pair of older releases
+    let old_pair = |addr: &str| {
+        addr.split_once(',')
+            .is_some_and(|(_, rest)| rest.contains(':'))
+    };
+    if slave.into_iter().chain(Some(master)).any(old_pair) {
+        return Err(Error::State(format!(
+            "bad node {}, pair a replica with its master as 'replica/master' instead of 'replica,master'",
+            arg
+        )));
+    }
+    Ok((slave, master))
+}
+
+fn warn_uneven(zones: &[String]) {
+    let mut count: BTreeMap<&str, usize> = BTreeMap::new();
+    for zone in zones {
+        *count.entry(zone).or_insert(0) += 1;
+    }
+    let max = count.values().max().cloned().unwrap_or(0);
+    let min = count.values().min().cloned().unwrap_or(0);
+    if max - min > 1 {
+        let count: Vec<String> = count.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        eprintln!(
+            "warning: masters are spread unevenly across zones: {}",
+            count.join(",")
+        );
     }
 }
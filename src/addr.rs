@@ -0,0 +1,134 @@
+use cluster::{AsResult, Error};
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr, ToSocketAddrs};
+
+#[test]
+fn test_parse_addr() {
+    let addr = Addr::parse("127.0.0.1:7000").unwrap();
+    assert_eq!(addr.host, "127.0.0.1");
+    assert_eq!(addr.port, 7000);
+    assert_eq!(addr.cport, None);
+    assert_eq!(addr.hostname, None);
+    assert_eq!(addr.to_string(), "127.0.0.1:7000");
+
+    let addr = Addr::parse("redis-0.redis.svc:6379").unwrap();
+    assert_eq!(addr.host, "redis-0.redis.svc");
+    assert_eq!(addr.port, 6379);
+
+    let addr = Addr::parse("127.0.0.1:7000@17000,redis-0.redis.svc").unwrap();
+    assert_eq!(addr.cport, Some(17000));
+    assert_eq!(addr.hostname, Some("redis-0.redis.svc".to_string()));
+    assert_eq!(addr.to_string(), "127.0.0.1:7000");
+
+    let addr = Addr::parse("10.0.0.3:6379@16379,,tls-port=0,shard-id=abc").unwrap();
+    assert_eq!(addr.cport, Some(16379));
+    assert_eq!(addr.hostname, None);
+
+    let addr = Addr::parse(":0@0").unwrap();
+    assert_eq!(addr.host, "");
+    assert_eq!(addr.port, 0);
+}
+
+#[test]
+fn test_parse_ipv6_addr() {
+    let addr = Addr::parse("[::1]:7000").unwrap();
+    assert_eq!(addr.host, "::1");
+    assert_eq!(addr.port, 7000);
+    assert_eq!(addr.to_string(), "[::1]:7000");
+
+    let addr = Addr::parse("fe80::1:7000").unwrap();
+    assert_eq!(addr.host, "fe80::1");
+    assert_eq!(addr.port, 7000);
+    assert_eq!(addr.to_string(), "[fe80::1]:7000");
+
+    let addr = Addr::parse("2001:db8::1:6379@16379").unwrap();
+    assert_eq!(addr.host, "2001:db8::1");
+    assert_eq!(addr.cport, Some(16379));
+
+    let addr = Addr::parse("[2001:db8::1]:6379@16379,node-1").unwrap();
+    assert_eq!(addr.host, "2001:db8::1");
+    assert_eq!(addr.hostname, Some("node-1".to_string()));
+}
+
+#[test]
+fn test_parse_bad_addr() {
+    assert!(Addr::parse("127.0.0.1").is_err());
+    assert!(Addr::parse("127.0.0.1:").is_err());
+    assert!(Addr::parse("127.0.0.1:port").is_err());
+    assert!(Addr::parse("127.0.0.1:70000").is_err());
+    assert!(Addr::parse("127.0.0.1:7000@bus").is_err());
+    assert!(Addr::parse("[::1:7000").is_err());
+    assert!(Addr::parse("[::1]7000").is_err());
+    assert!(Addr::parse("aa::bb").is_err());
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Addr {
+    pub host: String,
+    pub port: u16,
+    pub cport: Option<u16>,
+    pub hostname: Option<String>,
+}
+
+impl Addr {
+    pub fn parse(input: &str) -> AsResult<Addr> {
+        let mut fields = input.trim().split(',');
+        let main = fields.next().unwrap_or_default();
+        let hostname = fields
+            .next()
+            .filter(|x| !x.is_empty() && !x.contains('='))
+            .map(|x| x.to_string());
+
+        let (host_port, cport) = match main.rfind('@') {
+            Some(idx) => (&main[..idx], Some(parse_port(&main[idx + 1..])?)),
+            None => (main, None),
+        };
+        let (host, port) = if let Some(rest) = host_port.strip_prefix('[') {
+            let end = rest.find(']').ok_or(Error::BadAddr)?;
+            let port = rest[end + 1..].strip_prefix(':').ok_or(Error::BadAddr)?;
+            (&rest[..end], port)
+        } else {
+            let idx = host_port.rfind(':').ok_or(Error::BadAddr)?;
+            (&host_port[..idx], &host_port[idx + 1..])
+        };
+        if host.contains(':') && host.parse::<Ipv6Addr>().is_err() {
+            return Err(Error::BadAddr);
+        }
+        Ok(Addr {
+            host: host.to_string(),
+            port: parse_port(port)?,
+            cport,
+            hostname,
+        })
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        self.host.contains(':')
+    }
+
+    pub fn ip(&self) -> AsResult<String> {
+        if self.host.parse::<IpAddr>().is_ok() {
+            return Ok(self.host.clone());
+        }
+        (&*self.host, self.port)
+            .to_socket_addrs()
+            .map_err(Error::Io)?
+            .next()
+            .map(|x| x.ip().to_string())
+            .ok_or(Error::BadAddr)
+    }
+}
+
+impl fmt::Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_ipv6() {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+fn parse_port(port: &str) -> AsResult<u16> {
+    port.parse::<u16>().map_err(|_| Error::BadAddr)
+}
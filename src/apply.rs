@@ -0,0 +1,381 @@
+use addr::Addr;
+use cluster::{self, AsResult, Error, Node};
+use parser::{Flag, NodeEntry};
+use schedule::{self, Move};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::{thread, time};
+use util;
+
+#[cfg(test)]
+fn live() -> Vec<NodeEntry> {
+    ::parser::parse_nodes(
+        "\
+aaaa 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-8191
+bbbb 127.0.0.1:7001@17001 master - 0 0 2 connected 8192-16383
+cccc 127.0.0.1:7002@17002 slave aaaa 0 0 1 connected
+dddd 127.0.0.1:7003@17003 slave bbbb 0 0 2 connected
+",
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_parse_desired() {
+    let desired = Desired::parse(
+        "\
+masters:
+  - addr: 127.0.0.1:7000
+    slots: [0-8191]
+    replicas: [127.0.0.1:7002]
+  - addr: 127.0.0.1:7001
+    slots: [\"8192-16383\"]
+",
+    )
+    .unwrap();
+    assert_eq!(desired.masters.len(), 2);
+    assert_eq!(desired.masters[1].replicas.len(), 0);
+    assert!(Desired::parse("masters: [{addr: 127.0.0.1}]").is_err());
+    assert!(Desired::parse(
+        "masters: [{addr: '127.0.0.1:7000', slots: [0-10]}, {addr: '127.0.0.1:7001', slots: [10]}]"
+    )
+    .is_err());
+}
+
+#[test]
+fn test_diff_unchanged() {
+    let desired = Desired::parse(
+        "\
+masters:
+  - {addr: 127.0.0.1:7000, slots: [0-8191], replicas: [127.0.0.1:7002]}
+  - {addr: 127.0.0.1:7001, slots: [8192-16383], replicas: [127.0.0.1:7003]}
+",
+    )
+    .unwrap();
+    assert_eq!(diff(&desired, &live()).unwrap(), vec![]);
+}
+
+#[test]
+fn test_diff_changes() {
+    let desired = Desired::parse(
+        "\
+masters:
+  - {addr: 127.0.0.1:7000, slots: [0-8000], replicas: [127.0.0.1:7003]}
+  - {addr: 127.0.0.1:7001, slots: [8192-16383]}
+  - {addr: 127.0.0.1:7004, slots: [8001-8191], replicas: [127.0.0.1:7005]}
+",
+    )
+    .unwrap();
+    let actions = diff(&desired, &live()).unwrap();
+    assert_eq!(
+        actions,
+        vec![
+            Action::Meet("127.0.0.1:7004".to_string()),
+            Action::Meet("127.0.0.1:7005".to_string()),
+            Action::Move(
+                "127.0.0.1:7000".to_string(),
+                "127.0.0.1:7004".to_string(),
+                (8001..8192).collect()
+            ),
+            Action::Replicate("127.0.0.1:7003".to_string(), "127.0.0.1:7000".to_string()),
+            Action::Replicate("127.0.0.1:7005".to_string(), "127.0.0.1:7004".to_string()),
+            Action::Forget("127.0.0.1:7002".to_string(), "cccc".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_diff_rejects() {
+    let desired = Desired::parse(
+        "masters: [{addr: 127.0.0.1:7000, slots: [0-16383]}, {addr: 127.0.0.1:7002}]",
+    )
+    .unwrap();
+    assert!(diff(&desired, &live()).is_err());
+    let desired = Desired::parse("masters: [{addr: 127.0.0.1:7000, slots: [0-8191]}]").unwrap();
+    assert!(diff(&desired, &live()).is_err());
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Desired {
+    pub masters: Vec<DesiredMaster>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DesiredMaster {
+    pub addr: String,
+    #[serde(default)]
+    pub slots: Vec<SlotRange>,
+    #[serde(default)]
+    pub replicas: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum SlotRange {
+    Slot(usize),
+    Range(String),
+}
+
+impl Desired {
+    pub fn load(path: &str) -> AsResult<Desired> {
+        Desired::parse(&fs::read_to_string(path)?)
+    }
+
+    // yaml is a superset of json, so both are accepted
+    fn parse(content: &str) -> AsResult<Desired> {
+        let mut desired: Desired = serde_yaml::from_str(content)
+            .map_err(|err| Error::State(format!("bad desired state: {}", err)))?;
+        let mut seen = HashSet::new();
+        let mut owners: HashMap<usize, String> = HashMap::new();
+        for master in desired.masters.iter_mut() {
+            master.addr = Addr::parse(&master.addr)?.to_string();
+            for replica in master.replicas.iter_mut() {
+                *replica = Addr::parse(replica)?.to_string();
+            }
+            for addr in master.replicas.iter().chain(Some(&master.addr)) {
+                if !seen.insert(addr.clone()) {
+                    return Err(Error::State(format!("node {} is listed twice", addr)));
+                }
+            }
+            for slot in parse_slots(&master.slots)? {
+                if let Some(owner) = owners.insert(slot, master.addr.clone()) {
+                    return Err(Error::State(format!(
+                        "slot {} is assigned to both {} and {}",
+                        slot, owner, master.addr
+                    )));
+                }
+            }
+        }
+        Ok(desired)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Meet(String),
+    AddSlots(String, Vec<usize>),
+    Move(String, String, Vec<usize>),
+    Replicate(String, String),
+    Forget(String, String),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Meet(addr) => write!(f, "+ meet {}", addr),
+            Action::AddSlots(addr, slots) => {
+                write!(f, "+ add slots {} to {}", util::slot_ranges(slots), addr)
+            }
+            Action::Move(src, dst, slots) => write!(
+                f,
+                "~ move slots {} from {} to {}",
+                util::slot_ranges(slots),
+                src,
+                dst
+            ),
+            Action::Replicate(replica, master) => {
+                write!(f, "~ replicate {} to {}", replica, master)
+            }
+            Action::Forget(addr, id) => write!(f, "- forget {} {}", addr, id),
+        }
+    }
+}
+
+pub fn apply(seed: &str, desired: &Desired) -> AsResult<()> {
+    let seed = Node::new(seed.as_bytes())?;
+    let actions = diff(desired, &seed.entries()?)?;
+    if actions.is_empty() {
+        println!("cluster already matches the desired state");
+        return Ok(());
+    }
+    for action in &actions {
+        println!("{}", action);
+    }
+    if cluster::is_dry_run() {
+        return Ok(());
+    }
+
+    let meets: Vec<&String> = actions
+        .iter()
+        .filter_map(|x| match x {
+            Action::Meet(addr) => Some(addr),
+            _ => None,
+        })
+        .collect();
+    if !meets.is_empty() {
+        for addr in &meets {
+            seed.meet(&Node::new(addr.as_bytes())?)?;
+        }
+        wait_known(&seed, &meets)?;
+    }
+
+    let nodes: HashMap<String, Node> = seed.nodes()?.into_iter().map(|x| (x.addr(), x)).collect();
+    let node = |addr: &str| {
+        nodes
+            .get(addr)
+            .ok_or_else(|| Error::State(format!("node {} is unknown to the cluster", addr)))
+    };
+    let actions = diff(desired, &seed.entries()?)?;
+    let mut moves = Vec::new();
+    for action in &actions {
+        match action {
+            Action::AddSlots(addr, slots) => node(addr)?.add_slots(slots)?,
+            Action::Move(src, dst, slots) => {
+                let (src, dst) = (node(src)?, node(dst)?);
+                moves.extend(slots.iter().map(|slot| Move::new(src, dst, *slot)));
+            }
+            _ => {}
+        }
+    }
+    schedule::run(moves)?;
+    for action in &actions {
+        if let Action::Replicate(replica, master) = action {
+            let mut replica = node(replica)?.clone();
+            replica.slaveof = Some(node(master)?.name.clone());
+            replica.set_slave()?;
+        }
+    }
+    for action in &actions {
+        if let Action::Forget(addr, _) = action {
+            let forgotten = node(addr)?;
+            for other in nodes
+                .values()
+                .filter(|x| x.name != forgotten.name && !x.address().host.is_empty())
+            {
+                other.forget(forgotten)?;
+            }
+            // a forgotten node still knows the cluster and would meet it again
+            if let Err(err) = forgotten.reset() {
+                eprintln!(
+                    "warning: reset {} fail: {}, shut it down before it rejoins the cluster",
+                    addr, err
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn wait_known(seed: &Node, addrs: &[&String]) -> AsResult<()> {
+    println!("wait for {} new nodes to join...", addrs.len());
+    for _ in 0..60 {
+        let known: HashSet<String> = seed
+            .entries()?
+            .into_iter()
+            .filter(|x| !x.has(&Flag::Handshake))
+            .map(|x| x.addr.to_string())
+            .collect();
+        if addrs.iter().all(|x| known.contains(*x)) {
+            return Ok(());
+        }
+        thread::sleep(time::Duration::from_secs(1));
+    }
+    Err(Error::State(
+        "new nodes did not join the cluster in 60s".to_string(),
+    ))
+}
+
+fn diff(desired: &Desired, live: &[NodeEntry]) -> AsResult<Vec<Action>> {
+    let by_addr: HashMap<String, &NodeEntry> =
+        live.iter().map(|x| (x.addr.to_string(), x)).collect();
+    let mut actions = Vec::new();
+
+    for master in &desired.masters {
+        for addr in Some(&master.addr).into_iter().chain(&master.replicas) {
+            if !by_addr.contains_key(addr) {
+                actions.push(Action::Meet(addr.clone()));
+            }
+        }
+    }
+
+    let mut owners: HashMap<usize, &str> = HashMap::new();
+    for entry in live.iter().filter(|x| x.has(&Flag::Master)) {
+        for slot in entry.slot_list() {
+            owners.insert(slot, &entry.id);
+        }
+    }
+    let mut wanted: HashMap<usize, &str> = HashMap::new();
+    for master in &desired.masters {
+        if let Some(entry) = by_addr.get(&master.addr) {
+            if !entry.has(&Flag::Master) {
+                return Err(Error::State(format!(
+                    "{} is a replica, fail it over before making it a master",
+                    master.addr
+                )));
+            }
+        }
+        for slot in parse_slots(&master.slots)? {
+            wanted.insert(slot, &master.addr);
+        }
+    }
+    let ids: HashMap<&str, String> = live
+        .iter()
+        .map(|x| (x.id.as_str(), x.addr.to_string()))
+        .collect();
+    let mut adds: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    let mut moves: BTreeMap<(String, &str), Vec<usize>> = BTreeMap::new();
+    for slot in 0..util::CLUSTER_SLOTS {
+        match (owners.get(&slot), wanted.get(&slot)) {
+            (None, Some(dst)) => adds.entry(dst).or_default().push(slot),
+            (Some(src), Some(dst)) if ids[src] != *dst => {
+                moves.entry((ids[src].clone(), dst)).or_default().push(slot)
+            }
+            (Some(src), None) => {
+                return Err(Error::State(format!(
+                    "slot {} of {} is not assigned in the desired state",
+                    slot, ids[src]
+                )))
+            }
+            _ => {}
+        }
+    }
+    for (addr, slots) in adds {
+        actions.push(Action::AddSlots(addr.to_string(), slots));
+    }
+    for ((src, dst), slots) in moves {
+        actions.push(Action::Move(src, dst.to_string(), slots));
+    }
+
+    for master in &desired.masters {
+        let master_id = by_addr.get(&master.addr).map(|x| x.id.as_str());
+        for replica in &master.replicas {
+            let current = by_addr
+                .get(replica)
+                .and_then(|x| x.master_id.as_ref())
+                .map(|x| x.as_str());
+            if master_id.is_none() || current != master_id {
+                actions.push(Action::Replicate(replica.clone(), master.addr.clone()));
+            }
+        }
+    }
+
+    let listed: HashSet<&String> = desired
+        .masters
+        .iter()
+        .flat_map(|x| Some(&x.addr).into_iter().chain(&x.replicas))
+        .collect();
+    let mut forgets: Vec<&NodeEntry> = live
+        .iter()
+        .filter(|x| !x.has(&Flag::NoAddr) && !listed.contains(&x.addr.to_string()))
+        .collect();
+    forgets.sort_by_key(|x| x.addr.to_string());
+    for entry in forgets {
+        actions.push(Action::Forget(entry.addr.to_string(), entry.id.clone()));
+    }
+    Ok(actions)
+}
+
+fn parse_slots(ranges: &[SlotRange]) -> AsResult<Vec<usize>> {
+    let mut slots = Vec::new();
+    for range in ranges {
+        let range = match range {
+            SlotRange::Slot(slot) => slot.to_string(),
+            SlotRange::Range(range) => range.clone(),
+        };
+        let (start, end) = util::parse_slot_range(&range)
+            .ok_or_else(|| Error::State(format!("bad slot range {}", range)))?;
+        slots.extend(start..=end);
+    }
+    Ok(slots)
+}
@@ -0,0 +1,257 @@
+use cluster::{self, AsResult, Error, Node};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use util;
+
+#[cfg(test)]
+fn persistence(in_progress: &str, last_save: &str, status: &str) -> HashMap<String, String> {
+    let mut info = HashMap::new();
+    info.insert(
+        "rdb_bgsave_in_progress".to_string(),
+        in_progress.to_string(),
+    );
+    info.insert("rdb_last_save_time".to_string(), last_save.to_string());
+    info.insert("rdb_last_bgsave_status".to_string(), status.to_string());
+    info
+}
+
+#[test]
+fn test_saved() {
+    let mut running = false;
+    assert_eq!(
+        saved(100, &mut running, &persistence("0", "101", "ok")),
+        Ok(Some(101))
+    );
+    assert_eq!(
+        saved(100, &mut running, &persistence("0", "100", "ok")),
+        Ok(None)
+    );
+    assert!(saved(100, &mut running, &persistence("0", "100", "err")).is_err());
+    assert!(saved(100, &mut running, &HashMap::new()).is_err());
+    assert_eq!(
+        saved(100, &mut running, &persistence("1", "100", "ok")),
+        Ok(None)
+    );
+    assert!(running);
+    // a save seen running that ends in the second it started is done
+    assert_eq!(
+        saved(100, &mut running, &persistence("0", "100", "ok")),
+        Ok(Some(100))
+    );
+}
+
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub created: u64,
+    pub seed: String,
+    pub source: String,
+    pub nodes: Vec<ManifestNode>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestNode {
+    pub id: String,
+    pub addr: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub master_id: Option<String>,
+    pub slots: String,
+    pub rdb: String,
+    pub last_save_time: u64,
+}
+
+struct Target {
+    node: Node,
+    slots: Vec<usize>,
+    rdb: String,
+    before: u64,
+}
+
+pub struct Backup {
+    seed: String,
+    replicas: bool,
+    targets: Vec<Target>,
+}
+
+impl Backup {
+    pub fn new(addr: &str, replicas: bool) -> AsResult<Backup> {
+        let seed = Node::new(addr.as_bytes())?;
+        let nodes = seed.nodes()?;
+        let masters: HashMap<&str, &Node> = nodes
+            .iter()
+            .filter(|x| x.is_master() && !x.slots().is_empty())
+            .map(|x| (x.name.as_str(), x))
+            .collect();
+        let mut targets = Vec::new();
+        for node in &nodes {
+            let master = if replicas {
+                node.slaveof
+                    .as_ref()
+                    .and_then(|x| masters.get(x.as_str()).cloned())
+            } else {
+                masters.get(node.name.as_str()).cloned()
+            };
+            let master = match master {
+                Some(master) if !node.address().host.is_empty() => master,
+                _ => continue,
+            };
+            let dir = node.config_get("dir")?;
+            let file = node.config_get("dbfilename")?;
+            targets.push(Target {
+                node: node.clone(),
+                slots: master.slots(),
+                rdb: format!("{}/{}", dir.trim_end_matches('/'), file),
+                before: 0,
+            });
+        }
+        for master in masters.values() {
+            if replicas
+                && !targets
+                    .iter()
+                    .any(|x| x.node.slaveof.as_ref() == Some(&master.name))
+            {
+                eprintln!(
+                    "warning: master {} has no replica, its slots are not backed up",
+                    master.addr()
+                );
+            }
+        }
+        if targets.is_empty() {
+            return Err(Error::State("no node to back up".to_string()));
+        }
+        Ok(Backup {
+            seed: seed.addr(),
+            replicas,
+            targets,
+        })
+    }
+
+    pub fn run(&mut self, timeout: Duration) -> AsResult<Option<Manifest>> {
+        for target in self.targets.iter_mut() {
+            let info = target.node.redis_info()?;
+            if info.get("rdb_bgsave_in_progress").map(|x| x.as_str()) == Some("1") {
+                return Err(Error::State(format!(
+                    "{} is already running BGSAVE",
+                    target.node.addr()
+                )));
+            }
+            target.before = number(&info, "rdb_last_save_time")?;
+        }
+        for target in &self.targets {
+            target.node.bgsave()?;
+        }
+        if cluster::is_dry_run() {
+            return Ok(None);
+        }
+
+        let start = Instant::now();
+        let mut saved_at: HashMap<String, u64> = HashMap::new();
+        let mut running: HashMap<String, bool> = HashMap::new();
+        while saved_at.len() < self.targets.len() {
+            for target in &self.targets {
+                if saved_at.contains_key(&target.node.name) {
+                    continue;
+                }
+                let info = target.node.redis_info()?;
+                let seen = running.entry(target.node.name.clone()).or_insert(false);
+                let done = saved(target.before, seen, &info).map_err(|err| {
+                    Error::State(format!("BGSAVE of {} fail: {}", target.node.addr(), err))
+                })?;
+                if let Some(time) = done {
+                    println!(
+                        "{} saved {} after {:.2}s",
+                        target.node.addr(),
+                        target.rdb,
+                        start.elapsed().as_secs_f64()
+                    );
+                    saved_at.insert(target.node.name.clone(), time);
+                }
+            }
+            if saved_at.len() < self.targets.len() {
+                if start.elapsed() > timeout {
+                    return Err(Error::State(format!(
+                        "{} of {} nodes did not finish BGSAVE in {}s",
+                        self.targets.len() - saved_at.len(),
+                        self.targets.len(),
+                        timeout.as_secs()
+                    )));
+                }
+                thread::sleep(Duration::from_millis(500));
+            }
+        }
+
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or(0);
+        let nodes = self
+            .targets
+            .iter()
+            .map(|x| ManifestNode {
+                id: x.node.name.clone(),
+                addr: x.node.addr(),
+                master_id: x.node.slaveof.clone(),
+                slots: util::slot_ranges(&x.slots),
+                rdb: x.rdb.clone(),
+                last_save_time: saved_at[&x.node.name],
+            })
+            .collect();
+        Ok(Some(Manifest {
+            version: VERSION,
+            created,
+            seed: self.seed.clone(),
+            source: if self.replicas { "replica" } else { "master" }.to_string(),
+            nodes,
+        }))
+    }
+}
+
+impl Manifest {
+    pub fn write(&self, output: &str) -> io::Result<()> {
+        util::write_output(output, |out| self.write_to(out))
+    }
+
+    fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *out, self)?;
+        writeln!(out)
+    }
+}
+
+fn number(info: &HashMap<String, String>, key: &str) -> AsResult<u64> {
+    info.get(key)
+        .and_then(|x| x.trim().parse().ok())
+        .ok_or_else(|| Error::Protocol(format!("INFO has no {}", key)))
+}
+
+// the last save time once a BGSAVE started after before has finished, None while it runs.
+// rdb_last_save_time only has second resolution, so a save that ends in the second
+// before was taken is only trusted once it has been seen in progress.
+fn saved(
+    before: u64,
+    running: &mut bool,
+    info: &HashMap<String, String>,
+) -> Result<Option<u64>, String> {
+    let get = |key: &str| {
+        info.get(key)
+            .map(|x| x.trim())
+            .ok_or_else(|| format!("INFO has no {}", key))
+    };
+    if get("rdb_bgsave_in_progress")? != "0" {
+        *running = true;
+        return Ok(None);
+    }
+    if get("rdb_last_bgsave_status")? != "ok" {
+        return Err("rdb_last_bgsave_status is not ok".to_string());
+    }
+    let last: u64 = get("rdb_last_save_time")?
+        .parse()
+        .map_err(|_| "bad rdb_last_save_time".to_string())?;
+    if last > before || (*running && last == before) {
+        return Ok(Some(last));
+    }
+    Ok(None)
+}
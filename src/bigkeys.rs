@@ -0,0 +1,235 @@
+use addr::Addr;
+use cluster::{AsResult, Error, Node};
+use report::{self, Format};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::thread;
+use util;
+
+#[cfg(test)]
+fn big_key(key: &str, bytes: u64) -> BigKey {
+    BigKey {
+        key: key.to_string(),
+        kind: "string".to_string(),
+        bytes,
+        length: None,
+        slot: 0,
+        node: String::new(),
+        scanned: String::new(),
+    }
+}
+
+#[test]
+fn test_keep() {
+    let mut top = Vec::new();
+    for (key, bytes) in &[("a", 10), ("b", 30), ("c", 20), ("d", 5), ("e", 40)] {
+        keep(&mut top, big_key(key, *bytes), 3);
+    }
+    let keys: Vec<&str> = top.iter().map(|x| x.key.as_str()).collect();
+    assert_eq!(keys, vec!["e", "b", "c"]);
+    keep(&mut top, big_key("f", 1), 0);
+    assert!(top.is_empty());
+}
+
+#[derive(Debug, Serialize)]
+pub struct BigKey {
+    pub key: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub bytes: u64,
+    pub length: Option<u64>,
+    pub slot: usize,
+    pub node: String,
+    pub scanned: String,
+}
+
+struct Target {
+    addr: Addr,
+    owner: String,
+    replica: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BigKeys {
+    pub scanned: u64,
+    pub types: BTreeMap<String, Vec<BigKey>>,
+}
+
+pub struct Scan {
+    targets: Vec<Target>,
+    top: usize,
+    count: usize,
+    samples: usize,
+}
+
+impl Scan {
+    pub fn new(
+        addr: &str,
+        replicas: bool,
+        top: usize,
+        count: usize,
+        samples: usize,
+    ) -> AsResult<Scan> {
+        let seed = Node::new(addr.as_bytes())?;
+        let nodes = seed.nodes()?;
+        let masters: HashMap<&str, &Node> = nodes
+            .iter()
+            .filter(|x| x.is_master() && !x.slots().is_empty())
+            .map(|x| (x.name.as_str(), x))
+            .collect();
+        let mut targets = Vec::new();
+        for master in masters.values() {
+            let source = if replicas {
+                let replica = nodes.iter().find(|x| {
+                    x.slaveof.as_ref() == Some(&master.name) && !x.address().host.is_empty()
+                });
+                if replica.is_none() {
+                    eprintln!(
+                        "warning: master {} has no replica, scan the master instead",
+                        master.addr()
+                    );
+                }
+                replica.unwrap_or(master)
+            } else {
+                master
+            };
+            targets.push(Target {
+                addr: source.address(),
+                owner: master.addr(),
+                replica: source.name != master.name,
+            });
+        }
+        if targets.is_empty() {
+            return Err(Error::State("no master serves any slot".to_string()));
+        }
+        targets.sort_by(|a, b| a.owner.cmp(&b.owner));
+        Ok(Scan {
+            targets,
+            top,
+            count,
+            samples,
+        })
+    }
+
+    pub fn run(&self) -> AsResult<BigKeys> {
+        let (top, count, samples) = (self.top, self.count, self.samples);
+        let handles: Vec<_> = self
+            .targets
+            .iter()
+            .map(|target| {
+                let addr = target.addr.clone();
+                let owner = target.owner.clone();
+                let replica = target.replica;
+                thread::spawn(move || scan(addr, &owner, replica, top, count, samples))
+            })
+            .collect();
+        let mut result = BigKeys {
+            scanned: 0,
+            types: BTreeMap::new(),
+        };
+        for handle in handles {
+            let (scanned, keys) = handle.join().expect("scan worker panicked")?;
+            result.scanned += scanned;
+            for key in keys {
+                let top_keys = result.types.entry(key.kind.clone()).or_default();
+                keep(top_keys, key, top);
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl BigKeys {
+    pub fn write(&self, output: &str, format: &Format) -> io::Result<()> {
+        util::write_output(output, |out| self.write_to(out, format))
+    }
+
+    fn write_to<W: Write>(&self, out: &mut W, format: &Format) -> io::Result<()> {
+        match format {
+            Format::Csv => {
+                writeln!(out, "type,key,bytes,length,slot,node,scanned")?;
+                for key in self.types.values().flatten() {
+                    let length = key.length.map(|x| x.to_string()).unwrap_or_default();
+                    writeln!(
+                        out,
+                        "{},{},{},{},{},{},{}",
+                        key.kind,
+                        report::csv_escape(&key.key),
+                        key.bytes,
+                        length,
+                        key.slot,
+                        key.node,
+                        key.scanned
+                    )?;
+                }
+            }
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn scan(
+    addr: Addr,
+    owner: &str,
+    replica: bool,
+    top: usize,
+    count: usize,
+    samples: usize,
+) -> AsResult<(u64, Vec<BigKey>)> {
+    let node = Node::from_addr(addr);
+    // key commands on a replica are redirected to its master without READONLY
+    if replica {
+        node.readonly()?;
+    }
+    let mut types: HashMap<String, Vec<BigKey>> = HashMap::new();
+    let mut scanned = 0;
+    let mut cursor = 0;
+    loop {
+        let (next, keys) = node.scan(cursor, count)?;
+        if !keys.is_empty() {
+            let kinds = node.key_types(&keys)?;
+            // keys expired or deleted since SCAN have the type none
+            let keys: Vec<(Vec<u8>, String)> = keys
+                .into_iter()
+                .zip(kinds)
+                .filter(|(_, kind)| kind != "none")
+                .collect();
+            let sizes = node.key_sizes(&keys, samples)?;
+            scanned += keys.len() as u64;
+            for ((key, kind), (bytes, length)) in keys.into_iter().zip(sizes) {
+                let big = BigKey {
+                    slot: util::key_slot(&key),
+                    key: String::from_utf8_lossy(&key).into_owned(),
+                    kind: kind.clone(),
+                    bytes,
+                    length,
+                    node: owner.to_string(),
+                    scanned: node.addr(),
+                };
+                keep(types.entry(kind).or_default(), big, top);
+            }
+        }
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    eprintln!("scanned {} keys of {} on {}", scanned, owner, node.addr());
+    Ok((scanned, types.into_values().flatten().collect()))
+}
+
+// keeps the n biggest keys by memory usage, biggest first
+fn keep(top: &mut Vec<BigKey>, key: BigKey, n: usize) {
+    let idx = top
+        .iter()
+        .position(|x| x.bytes < key.bytes)
+        .unwrap_or(top.len());
+    if idx < n {
+        top.insert(idx, key);
+    }
+    top.truncate(n);
+}
@@ -0,0 +1,217 @@
+use cluster::{AsResult, Node};
+use parser::{Flag, NodeEntry};
+use std::collections::{BTreeMap, HashMap};
+use util::{self, CLUSTER_SLOTS};
+
+#[cfg(test)]
+fn view(output: &str) -> Vec<NodeEntry> {
+    ::parser::parse_nodes(output).unwrap()
+}
+
+#[test]
+fn test_check_healthy() {
+    let a = "\
+aaaa 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-8191
+bbbb 127.0.0.1:7001@17001 master - 0 0 2 connected 8192-16383
+cccc 127.0.0.1:7002@17002 slave aaaa 0 0 1 connected
+dddd 127.0.0.1:7003@17003 slave bbbb 0 0 2 connected
+";
+    let entries = view(a);
+    let views = vec![
+        ("127.0.0.1:7000".to_string(), Ok(view(a))),
+        ("127.0.0.1:7001".to_string(), Ok(view(a))),
+    ];
+    let health = Health::inspect(&entries, &views);
+    assert!(health.ok(), "{:?}", health.items);
+}
+
+#[test]
+fn test_check_problems() {
+    let a = "\
+aaaa 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-8191 [100->-bbbb]
+bbbb 127.0.0.1:7001@17001 master - 0 0 1 connected 8192-16000
+cccc 127.0.0.1:7002@17002 slave,fail aaaa 0 0 1 disconnected
+";
+    let b = "\
+aaaa 127.0.0.1:7000@17000 master - 0 0 1 connected 0-8190
+bbbb 127.0.0.1:7001@17001 myself,master - 0 0 1 connected 8191-16000 [100-<-aaaa]
+cccc 127.0.0.1:7002@17002 slave,fail? aaaa 0 0 1 disconnected
+";
+    let entries = view(a);
+    let views = vec![
+        ("127.0.0.1:7000".to_string(), Ok(view(a))),
+        ("127.0.0.1:7001".to_string(), Ok(view(b))),
+        (
+            "127.0.0.1:7002".to_string(),
+            Err("connection refused".to_string()),
+        ),
+    ];
+    let health = Health::inspect(&entries, &views);
+    assert!(!health.ok());
+    let errors: Vec<&str> = health
+        .items
+        .iter()
+        .filter(|x| !x.ok)
+        .map(|x| x.msg.as_str())
+        .collect();
+    assert!(errors.iter().any(|x| x.contains("unreachable")));
+    assert!(errors.iter().any(|x| x.contains("disagrees on 1 slots")));
+    assert!(errors.iter().any(|x| x.contains("16001-16383")));
+    assert!(errors.iter().any(|x| x.contains("slot 100 migrating")));
+    assert!(errors.iter().any(|x| x.contains("slot 100 importing")));
+    assert!(errors.iter().any(|x| x.contains("flagged fail")));
+    assert!(errors.iter().any(|x| x.contains("has no replica")));
+    assert!(errors.iter().any(|x| x.contains("config epoch 1")));
+}
+
+#[derive(Debug)]
+pub struct Item {
+    pub ok: bool,
+    pub msg: String,
+}
+
+#[derive(Debug)]
+pub struct Health {
+    pub items: Vec<Item>,
+}
+
+impl Health {
+    pub fn new(addr: &str) -> AsResult<Health> {
+        let seed = Node::new(addr.as_bytes())?;
+        let entries = seed.entries()?;
+        let mut views = Vec::new();
+        for entry in &entries {
+            if entry.has(&Flag::NoAddr) || entry.has(&Flag::Handshake) {
+                continue;
+            }
+            let view = Node::from_addr(entry.addr.clone())
+                .entries()
+                .map_err(|err| err.to_string());
+            views.push((entry.addr.to_string(), view));
+        }
+        Ok(Health::inspect(&entries, &views))
+    }
+
+    pub fn inspect(
+        entries: &[NodeEntry],
+        views: &[(String, Result<Vec<NodeEntry>, String>)],
+    ) -> Health {
+        let mut health = Health { items: vec![] };
+        let slot_map = slot_owners(entries);
+
+        for (addr, view) in views {
+            let view = match view {
+                Ok(view) => view,
+                Err(err) => {
+                    health.fail(format!("{} is unreachable: {}", addr, err));
+                    continue;
+                }
+            };
+            let other = slot_owners(view);
+            let diff = (0..CLUSTER_SLOTS)
+                .filter(|slot| slot_map.get(slot) != other.get(slot))
+                .count();
+            if diff == 0 {
+                health.pass(format!("{} agrees with the slot map", addr));
+            } else {
+                health.fail(format!("{} disagrees on {} slots", addr, diff));
+            }
+            for entry in view.iter().filter(|x| x.has(&Flag::Myself)) {
+                for (slot, id) in &entry.migrating {
+                    health.fail(format!("{} has slot {} migrating to {}", addr, slot, id));
+                }
+                for (slot, id) in &entry.importing {
+                    health.fail(format!("{} has slot {} importing from {}", addr, slot, id));
+                }
+            }
+            for entry in view.iter().filter(|x| x.is_failing()) {
+                let flag = if entry.has(&Flag::Fail) {
+                    "fail"
+                } else {
+                    "pfail"
+                };
+                health.fail(format!(
+                    "{} {} flagged {} by {}",
+                    entry.addr, entry.id, flag, addr
+                ));
+            }
+        }
+
+        let uncovered: Vec<usize> = (0..CLUSTER_SLOTS)
+            .filter(|slot| !slot_map.contains_key(slot))
+            .collect();
+        if uncovered.is_empty() {
+            health.pass(format!("all {} slots covered", CLUSTER_SLOTS));
+        } else {
+            health.fail(format!(
+                "{} slots not covered: {}",
+                uncovered.len(),
+                util::slot_ranges(&uncovered)
+            ));
+        }
+
+        let masters: Vec<&NodeEntry> = entries
+            .iter()
+            .filter(|x| x.has(&Flag::Master) && x.slot_count() > 0)
+            .collect();
+        for master in &masters {
+            let replicas = entries
+                .iter()
+                .filter(|x| x.master_id.as_ref() == Some(&master.id) && !x.is_failing())
+                .count();
+            if replicas == 0 {
+                health.fail(format!(
+                    "master {} {} has no replica",
+                    master.addr, master.id
+                ));
+            }
+        }
+
+        let mut epochs: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+        for master in &masters {
+            epochs
+                .entry(master.config_epoch)
+                .or_default()
+                .push(master.addr.to_string());
+        }
+        for (epoch, addrs) in epochs {
+            if addrs.len() > 1 {
+                health.fail(format!(
+                    "config epoch {} is shared by {}",
+                    epoch,
+                    addrs.join(",")
+                ));
+            }
+        }
+        health
+    }
+
+    pub fn ok(&self) -> bool {
+        self.items.iter().all(|x| x.ok)
+    }
+
+    pub fn print(&self) {
+        for item in &self.items {
+            let tag = if item.ok { "[OK]" } else { "[ERR]" };
+            println!("{} {}", tag, item.msg);
+        }
+    }
+
+    fn pass(&mut self, msg: String) {
+        self.items.push(Item { ok: true, msg });
+    }
+
+    fn fail(&mut self, msg: String) {
+        self.items.push(Item { ok: false, msg });
+    }
+}
+
+fn slot_owners(entries: &[NodeEntry]) -> HashMap<usize, String> {
+    let mut owners = HashMap::new();
+    for entry in entries.iter().filter(|x| x.has(&Flag::Master)) {
+        for slot in entry.slot_list() {
+            owners.insert(slot, entry.id.clone());
+        }
+    }
+    owners
+}
@@ -1,28 +1,166 @@
-use redis::Connection;
+use addr::Addr;
+use parser::{self, Flag, NodeEntry};
+use rebalance::Rebalance;
+use redis::{
+    ClientTlsConfig, Connection, ConnectionAddr, ConnectionInfo, ErrorKind, FromRedisValue,
+    RedisConnectionInfo, RedisError, TlsCertificates, Value,
+};
+use schedule::{self, Move};
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
+use std::error;
 use std::fmt;
+use std::io;
 use std::rc::Rc;
 use std::result;
 use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::{thread, time};
 use util;
-pub static COLON_STR: &'static str = ":";
+use zone;
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::SeqCst);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Auth {
+    pub user: Option<String>,
+    pub password: String,
+}
+
+static AUTH: OnceLock<Auth> = OnceLock::new();
+
+pub fn set_auth(auth: Auth) {
+    let _ = AUTH.set(auth);
+}
+
+fn auth() -> Option<&'static Auth> {
+    AUTH.get()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Tls {
+    pub ca: Option<Vec<u8>>,
+    pub cert: Option<Vec<u8>>,
+    pub key: Option<Vec<u8>>,
+    pub insecure: bool,
+}
+
+impl Tls {
+    fn certificates(&self) -> TlsCertificates {
+        let client_tls = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => Some(ClientTlsConfig {
+                client_cert: cert.clone(),
+                client_key: key.clone(),
+            }),
+            _ => None,
+        };
+        TlsCertificates {
+            client_tls,
+            root_cert: self.ca.clone(),
+        }
+    }
+}
+
+static TLS: OnceLock<Tls> = OnceLock::new();
+
+pub fn set_tls(tls: Tls) {
+    let _ = TLS.set(tls);
+}
+
+fn tls() -> Option<&'static Tls> {
+    TLS.get()
+}
+
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub batch: usize,
+    pub timeout: usize,
+    pub replace: bool,
+    pub retries: usize,
+    pub parallel: usize,
+    pub per_node: usize,
+}
+
+impl Default for Migration {
+    fn default() -> Migration {
+        Migration {
+            batch: 100,
+            timeout: 5000,
+            replace: false,
+            retries: 3,
+            parallel: 1,
+            per_node: 1,
+        }
+    }
+}
+
+static MIGRATION: OnceLock<Migration> = OnceLock::new();
+
+pub fn set_migration(migration: Migration) {
+    let _ = MIGRATION.set(migration);
+}
+
+pub fn migration() -> Migration {
+    MIGRATION.get().cloned().unwrap_or_default()
+}
+
 #[test]
 fn test_node_init() {
     let node = Node::new(b"127.0.0.1:8888").unwrap();
-    assert_eq!(node.ip, "127.0.0.1");
-    assert_eq!(node.port, "8888");
+    assert_eq!(node.address().host, "127.0.0.1");
+    assert_eq!(node.address().port, 8888);
+    assert_eq!(node.zone(), None);
+    let node = Node::new(b"127.0.0.1:8888@zone=a").unwrap();
+    assert_eq!(node.addr(), "127.0.0.1:8888");
+    assert_eq!(node.zone(), Some("a".to_string()));
 }
+#[test]
+fn test_unreachable_node() {
+    let node = Node::new(b"127.0.0.1:1").unwrap();
+    match node.info() {
+        Err(Error::Connect(addr, _)) => assert_eq!(addr, "127.0.0.1:1"),
+        other => panic!("expect connect error, got {:?}", other),
+    }
+    assert!(Node::new(b":0").unwrap().nodes().is_err());
+    assert!(Node::new(b"127.0.0.1").is_err());
+}
+#[test]
+fn test_reply_code() {
+    let busy = Error::Reply(
+        "127.0.0.1:7000".to_string(),
+        "BUSYKEY: Target key name already exists.".to_string(),
+    );
+    assert!(busy.is_reply("BUSYKEY"));
+    assert!(!busy.is_reply("IOERR"));
+    assert!(!Error::State("BUSYKEY".to_string()).is_reply("BUSYKEY"));
+}
+#[test]
+fn test_estimate() {
+    assert_eq!(estimate(10, &[]), 0);
+    assert_eq!(estimate(10, &[None]), 0);
+    assert_eq!(estimate(3, &[Some(10), Some(11), None]), 31);
+}
+
 #[test]
 fn test_consistency() {
-    let mut nodes = Vec::new();
-    nodes.push(Node::new(b"127.0.0.1:7000").unwrap());
-    nodes.push(Node::new(b"127.0.0.1:7001").unwrap());
-    nodes.push(Node::new(b"127.0.0.1:7002").unwrap());
-    nodes.push(Node::new(b"127.0.0.1:7003").unwrap());
-    nodes.push(Node::new(b"127.0.0.1:7004").unwrap());
+    let nodes = vec![
+        Node::new(b"127.0.0.1:7000").unwrap(),
+        Node::new(b"127.0.0.1:7001").unwrap(),
+        Node::new(b"127.0.0.1:7002").unwrap(),
+        Node::new(b"127.0.0.1:7003").unwrap(),
+        Node::new(b"127.0.0.1:7004").unwrap(),
+    ];
     let cluster = Cluster::new(nodes);
-    assert_eq!(cluster.consistency(), true);
+    assert!(cluster.consistency());
 }
 #[derive(Debug, PartialEq, Clone)]
 pub enum Role {
@@ -32,7 +170,7 @@ pub enum Role {
 
 impl PartialEq for Node {
     fn eq(&self, other: &Node) -> bool {
-        self.ip == other.ip && self.port == other.port
+        self.addr.host == other.addr.host && self.addr.port == other.addr.port
     }
 }
 impl fmt::Debug for Node {
@@ -41,7 +179,13 @@ impl fmt::Debug for Node {
         write!(
             f,
             "Node{{name: {:?} ,ip: {},port: {},slots: {},self:{:?},role:{:?},slaveof:{:?} }}",
-            self.name, self.ip, self.port, slot_num, self.myself, self.role, self.slaveof
+            self.name,
+            self.addr.host,
+            self.addr.port,
+            slot_num,
+            self.myself,
+            self.role,
+            self.slaveof
         )
     }
 }
@@ -64,7 +208,13 @@ impl Cluster {
         let mut node_slot: HashMap<usize, Node> = HashMap::new();
         for node in &self.nodes {
             let mut slot_num = 0;
-            let nodes = node.nodes();
+            let nodes = match node.nodes() {
+                Ok(nodes) => nodes,
+                Err(err) => {
+                    eprintln!("get nodes of {} fail: {}", node.addr(), err);
+                    return false;
+                }
+            };
             for node in nodes.into_iter() {
                 for slot in node.slots.clone().into_inner() {
                     let sv = node_slot.entry(slot).or_insert_with(|| node.clone());
@@ -75,7 +225,7 @@ impl Cluster {
                 }
             }
 
-            if slot_num != 16384 {
+            if slot_num != util::CLUSTER_SLOTS {
                 return false;
             }
         }
@@ -83,145 +233,189 @@ impl Cluster {
         true
     }
 
-    pub fn check(&self) -> Result<(), Error> {
+    pub fn check(&self) -> AsResult<()> {
         for node in &self.nodes {
-            let nodes_info = node.info();
-            assert_eq!(
-                nodes_info.get("cluster_known_nodes").cloned(),
-                Some("1".to_string())
-            );
+            let nodes_info = node.info()?;
+            if nodes_info.get("cluster_known_nodes").map(|x| x.as_str()) != Some("1") {
+                return Err(Error::State(format!(
+                    "node {} already knows other nodes",
+                    node.addr()
+                )));
+            }
         }
         Ok(())
     }
 
-    pub fn delete_node(&self, del_node: &Node) {
+    pub fn delete_node(&self, del_node: &Node) -> AsResult<()> {
         if del_node.is_master() {
             let nodes: Vec<&Node> = self
                 .nodes
                 .iter()
                 .filter(|&x| x.role == Some(Role::Master) && x.name != del_node.name)
                 .collect();
-            let slots = del_node.slots.borrow();
-            let slot_count = slots.len();
-            let mut dispatch = util::divide(slot_count, nodes.len());
+            let slots = del_node.slots();
+            if nodes.is_empty() && !slots.is_empty() {
+                return Err(Error::State(format!(
+                    "no other master to take over slots of {}",
+                    del_node.addr()
+                )));
+            }
+            let mut dispatch = util::divide(slots.len(), nodes.len());
             let mut start = 0;
+            let mut moves = Vec::new();
             for node in nodes {
-                println!("start migrate from {:?} to {:?}", del_node, node);
                 let count = dispatch.pop().unwrap();
-                let migrate = &slots[start..start + count];
-                // todo:MIGRATE DATA
-                for slot in migrate.iter() {
-                    migrate_slot(del_node, node, *slot);
+                println!(
+                    "migrate {} slots from {} to {}",
+                    count,
+                    del_node.addr(),
+                    node.addr()
+                );
+                for slot in &slots[start..start + count] {
+                    moves.push(Move::new(del_node, node, *slot));
                 }
                 start += count;
-                println!("stop migrate from {:?} to {:?}", del_node, node);
             }
-        }
-
-        let forget = |node: &Node| {
-            for n in &self.nodes {
-                if n.name == node.name {
-                    continue;
-                }
-                if Some(node.name.to_string()) == n.slaveof {
-                    // todo forget slav
-                    continue;
-                }
-                println!("node {:?} forget {:?}", n, node);
-                n.forget(&node);
+            // the journal only records the slot moves, the node is forgotten below
+            if let Err(err) = schedule::run(moves) {
+                eprintln!(
+                    "run delete again after resume to forget {}",
+                    del_node.addr()
+                );
+                return Err(err);
             }
-        };
-        forget(del_node);
-    }
+        }
 
-    pub fn node(&self, node: &str) -> Option<&Node> {
         for n in &self.nodes {
-            if n.addr() == node {
-                return Some(n);
+            if n.name == del_node.name {
+                continue;
             }
+            if Some(del_node.name.to_string()) == n.slaveof {
+                // todo forget slav
+                continue;
+            }
+            println!("node {:?} forget {:?}", n, del_node);
+            n.forget(del_node)?;
         }
-        None
+        Ok(())
     }
 
-    pub fn fill_slots(&self) {
-        let slots: HashSet<usize> = self
-            .nodes
+    pub fn node(&self, node: &str) -> Option<&Node> {
+        let addr = Addr::parse(node).ok()?;
+        self.nodes
             .iter()
-            .filter(|x| x.is_master())
-            .map(|x| x.slots.clone().into_inner())
-            .flatten()
-            .collect();
-        let all_slots: HashSet<usize> = (1..16384).collect();
-        let miss = all_slots
+            .find(|n| n.addr.host == addr.host && n.addr.port == addr.port)
+    }
+
+    pub fn fill_slots(&self) -> AsResult<()> {
+        let masters: Vec<&Node> = self.nodes.iter().filter(|x| x.is_master()).collect();
+        let slots: HashSet<usize> = masters.iter().flat_map(|x| x.slots()).collect();
+        let all_slots: HashSet<usize> = (0..util::CLUSTER_SLOTS).collect();
+        let mut miss = all_slots
             .difference(&slots)
             .cloned()
             .collect::<Vec<usize>>();
-        let mut dist = util::divide(miss.len(), self.nodes.len());
+        if miss.is_empty() {
+            return Ok(());
+        }
+        if masters.is_empty() {
+            return Err(Error::State(
+                "no master to take uncovered slots".to_string(),
+            ));
+        }
+        miss.sort();
+        let mut dist = util::divide(miss.len(), masters.len());
         let mut idx = 0;
-        for node in self.nodes.iter().filter(|x| x.is_master()) {
+        for node in masters {
             let num = dist.pop().unwrap();
             if num == 0 {
                 continue;
             }
             let slots = &miss[idx..idx + num];
-            node.add_slots(slots);
+            node.add_slots(slots)?;
             idx += num;
         }
+        Ok(())
     }
 
-    pub fn fix_slots(&self) {
+    pub fn fix_slots(&self) -> AsResult<()> {
+        let mut failed = 0;
         for master in self.nodes.iter().filter(|x| x.is_master()) {
-            master.fix_node();
+            if let Err(err) = master.fix_node() {
+                eprintln!("fix node {} fail: {}", master.addr(), err);
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            return Err(Error::State(format!(
+                "{} node(s) could not be fixed",
+                failed
+            )));
         }
+        Ok(())
     }
 
-    pub fn reshard(&self) {
-        let master: Vec<Node> = self
-            .nodes
-            .iter()
-            .filter(|x| x.is_master())
-            .cloned()
-            .collect();
-        let mut slots = vec![];
-        let dist: Vec<(Node, usize)> = master
-            .iter()
-            .cloned()
-            .zip(util::divide(16384, master.len()))
-            .collect();
-        for (node, num) in &dist {
-            let delta = node.slots.borrow().len() as i64 - *num as i64;
-            if delta > 0 {
-                for _i in 0..delta {
-                    slots.push((node.clone(), node.slots.borrow_mut().pop().unwrap()))
-                }
-            }
+    pub fn reshard(&self, rebalance: &Rebalance) -> AsResult<()> {
+        let moves = rebalance.plan(&self.nodes)?;
+        schedule::run(moves)
+    }
+}
+
+// fills the open slots of every entry a seed listed from that node's own CLUSTER NODES
+pub fn fill_open_slots(entries: &mut [NodeEntry]) {
+    for entry in entries.iter_mut().filter(|x| !x.has(&Flag::Myself)) {
+        if entry.addr.host.is_empty() || entry.has(&Flag::NoAddr) {
+            continue;
         }
-        for (node, num) in &dist {
-            let mut delta = node.slots.borrow().len() as i64 - *num as i64;
-            while delta < 0 {
-                let (src, slot) = slots.pop().unwrap();
-                migrate_slot(&src, node, slot);
-                delta += 1;
-            }
+        match Node::from_addr(entry.addr.clone()).entries() {
+            Ok(own) => entry.open_slots_from(own),
+            Err(err) => eprintln!("warning: read open slots of {} fail: {}", entry.addr, err),
         }
     }
 }
 
-pub fn migrate_slot(src: &Node, dst: &Node, slot: usize) {
-    dst.setslot("IMPORTING", src.name.clone(), slot);
-    src.setslot("MIGRATING", dst.name.clone(), slot);
-    while let Some(key) = src.keysinslot(slot) {
-        src.migrate(&*dst.ip, &*dst.port, key);
+pub fn migrate_slot(src: &Node, dst: &Node, slot: usize) -> AsResult<()> {
+    if is_dry_run() {
+        println!(
+            "[dry-run] migrate slot {} ({} keys) from {} {} to {} {}",
+            slot,
+            src.countkeysinslot(slot)?,
+            src.addr(),
+            src.name,
+            dst.addr(),
+            dst.name
+        );
+        return Ok(());
     }
-    src.setslot("NODE", dst.name.clone(), slot);
-    dst.setslot("NODE", dst.name.clone(), slot);
+    let opts = migration();
+    dst.setslot("IMPORTING", src.name.clone(), slot)?;
+    src.setslot("MIGRATING", dst.name.clone(), slot)?;
+    loop {
+        let keys = src.keysinslot(slot, opts.batch)?;
+        if keys.is_empty() {
+            break;
+        }
+        src.migrate(dst, slot, &keys, &opts)?;
+    }
+    let left = src.countkeysinslot(slot)?;
+    if left != 0 {
+        return Err(Error::State(format!(
+            "slot {} still has {} keys on {} after migration",
+            slot,
+            left,
+            src.addr()
+        )));
+    }
+    dst.setslot("NODE", dst.name.clone(), slot)?;
+    src.setslot("NODE", dst.name.clone(), slot)?;
+    Ok(())
 }
 
 #[derive(Clone)]
 pub struct Node {
     pub name: String,
-    pub ip: String,
-    pub port: String,
+    addr: Addr,
+    zone: Option<String>,
     role: Option<Role>,
     myself: Option<bool>,
     pub slaveof: Option<String>,
@@ -229,206 +423,191 @@ pub struct Node {
     slots: RefCell<Vec<usize>>,
     migrating: HashMap<usize, String>,
     importing: HashMap<usize, String>,
-    conn: Rc<Option<Connection>>,
+    conn: Rc<RefCell<Option<Connection>>>,
 }
 
 impl Node {
     pub fn new(addr: &[u8]) -> AsResult<Node> {
-        let content = String::from_utf8_lossy(addr);
-        let items: Vec<&str> = content.split(COLON_STR).collect();
-        if items.len() != 2 {
-            Err(Error::BadAddr)
-        } else {
-            let ip = items[0];
-            let port = items[1];
-
-            let con = if ip != "" {
-                let addr = "redis://".to_string() + ip + ":" + port;
-                Some(
-                    redis::Client::open(&*addr)
-                        .unwrap()
-                        .get_connection()
-                        .unwrap(),
-                )
-            } else {
-                None
-            };
-            Ok(Node {
-                name: str::from_utf8(addr).unwrap().to_string(),
-                role: None,
-                port: port.to_string(),
-                ip: ip.to_string(),
-                slaveof: None,
-                myself: None,
-                nodes: RefCell::new(HashMap::new()),
-                slots: RefCell::new(vec![]),
-                migrating: HashMap::new(),
-                importing: HashMap::new(),
-                conn: Rc::new(con),
-            })
+        let content = str::from_utf8(addr).map_err(|_| Error::BadAddr)?;
+        let (content, zone) = zone::split_labels(content)?;
+        let mut node = Node::from_addr(Addr::parse(&content)?);
+        node.zone = zone;
+        Ok(node)
+    }
+
+    pub fn from_addr(addr: Addr) -> Node {
+        Node {
+            name: addr.to_string(),
+            role: None,
+            addr,
+            zone: None,
+            slaveof: None,
+            myself: None,
+            nodes: RefCell::new(HashMap::new()),
+            slots: RefCell::new(vec![]),
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+            conn: Rc::new(RefCell::new(None)),
         }
     }
 
-    pub fn connect(&mut self) {
-        let nodes = self.nodes();
+    pub fn connect(&mut self) -> AsResult<()> {
+        let nodes = self.nodes()?;
         for node in &nodes {
             if let Some(_t) = node.myself {
                 self.name = node.name.clone();
+                self.role = node.role.clone();
+                self.myself = node.myself;
                 self.slaveof = node.slaveof.clone();
                 self.slots = node.slots.clone();
+                self.migrating = node.migrating.clone();
+                self.importing = node.importing.clone();
             }
         }
+        Ok(())
     }
 
-    pub fn fix_node(&self) {
+    pub fn fix_node(&self) -> AsResult<()> {
         for (slot, nodeid) in &self.migrating {
-            let target = self.nodes.borrow().get(nodeid).cloned().unwrap();
+            let target = self.node_by_id(nodeid)?;
             if target.importing.contains_key(slot) {
-                migrate_slot(self, &target, *slot);
+                migrate_slot(self, &target, *slot)?;
                 continue;
             }
-            self.setslot_stable(*slot);
+            self.setslot_stable(*slot)?;
         }
         for (slot, nodeid) in &self.importing {
-            let target = self.nodes.borrow().get(nodeid).cloned().unwrap();
-            if target.importing.contains_key(slot) {
-                migrate_slot(self, &target, *slot);
+            let target = self.node_by_id(nodeid)?;
+            if target.migrating.contains_key(slot) {
+                migrate_slot(&target, self, *slot)?;
                 continue;
             }
-            self.setslot_stable(*slot);
+            self.setslot_stable(*slot)?;
         }
+        Ok(())
     }
 
-    pub fn info(&self) -> HashMap<String, String> {
-        let mut node_infos = HashMap::new();
-        let a = self.conn.as_ref().as_ref().unwrap();
-        let info: String = redis::cmd("CLUSTER").arg("INFO").query(a).unwrap();
-        let infos: Vec<String> = info.split("\r\n").map(|x| x.to_string()).collect();
+    // the slots this node migrates or imports, only its own CLUSTER NODES line shows them
+    pub fn open_slots(&self) -> AsResult<Vec<usize>> {
+        let mut slots: Vec<usize> = self
+            .entries()?
+            .into_iter()
+            .filter(|x| x.has(&Flag::Myself))
+            .flat_map(|x| x.migrating.into_keys().chain(x.importing.into_keys()))
+            .collect();
+        slots.sort();
+        slots.dedup();
+        Ok(slots)
+    }
 
-        for mut info in infos.into_iter() {
-            let kv: Vec<String> = info.split(':').map(|x| x.to_string()).collect();
-            if kv.len() == 2 {
-                node_infos.insert(kv[0].clone(), kv[1].clone());
-            }
+    pub fn info(&self) -> AsResult<HashMap<String, String>> {
+        let info: String = self.query(redis::cmd("CLUSTER").arg("INFO"))?;
+        Ok(parse_info(&info))
+    }
+
+    pub fn redis_info(&self) -> AsResult<HashMap<String, String>> {
+        let info: String = self.query(&redis::cmd("INFO"))?;
+        Ok(parse_info(&info))
+    }
+
+    pub fn config_get(&self, name: &str) -> AsResult<String> {
+        let pair: Vec<String> = self.query(redis::cmd("CONFIG").arg("GET").arg(name))?;
+        pair.into_iter().nth(1).ok_or_else(|| {
+            Error::Protocol(format!("{}: CONFIG GET {} is empty", self.addr(), name))
+        })
+    }
+
+    pub fn bgsave(&self) -> AsResult<()> {
+        if self.dry_run("BGSAVE".to_string()) {
+            return Ok(());
         }
-        node_infos
+        self.query(&redis::cmd("BGSAVE"))
     }
 
     pub fn set_role(&mut self, role: Role) {
         self.role = Some(role);
     }
 
-    pub fn set_slave(&self) {
-        let node_id = self.slaveof.clone().unwrap();
-        println!("set {}  replicate to {}", self.ip, node_id);
-        if let Some(conn) = self.conn.as_ref() {
-            let _: () = redis::cmd("CLUSTER")
-                .arg("REPLICATE")
-                .arg(&*node_id)
-                .query(conn)
-                .expect("cluster replicate err");
+    pub fn set_slave(&self) -> AsResult<()> {
+        let node_id = match self.slaveof {
+            Some(ref node_id) => node_id.clone(),
+            None => {
+                return Err(Error::State(format!(
+                    "node {} has no master to replicate",
+                    self.addr()
+                )))
+            }
+        };
+        if self.dry_run(format!("CLUSTER REPLICATE {}", node_id)) {
+            return Ok(());
         }
+        println!("set {}  replicate to {}", self.addr.host, node_id);
+        self.query(redis::cmd("CLUSTER").arg("REPLICATE").arg(&*node_id))
     }
 
     pub fn addr(&self) -> String {
-        self.ip.clone() + ":" + &*self.port
+        self.address().to_string()
+    }
+
+    pub fn address(&self) -> Addr {
+        self.addr.clone()
+    }
+
+    pub fn zone(&self) -> Option<String> {
+        self.zone.clone().or_else(|| zone::lookup(&self.address()))
     }
 
-    pub fn add_slots(&self, slots: &[usize]) {
-        if let Some(conn) = self.conn.as_ref() {
-            let _: () = redis::cmd("cluster")
-                .arg("addslots")
-                .arg(slots)
-                .query(conn)
-                .expect("add slots err");
+    pub fn add_slots(&self, slots: &[usize]) -> AsResult<()> {
+        if self.dry_run(format!("CLUSTER ADDSLOTS {}", util::slot_ranges(slots))) {
+            return Ok(());
         }
+        self.query(redis::cmd("CLUSTER").arg("ADDSLOTS").arg(slots))
     }
 
-    pub fn set_config_epoch(&self, epoch: usize) {
-        if let Some(conn) = self.conn.as_ref() {
-            let _: () = redis::cmd("CLUSTER")
-                .arg("SET-CONFIG-EPOCH")
-                .arg(epoch)
-                .query(conn)
-                .expect("set config epoch err");
+    pub fn set_config_epoch(&self, epoch: usize) -> AsResult<()> {
+        if self.dry_run(format!("CLUSTER SET-CONFIG-EPOCH {}", epoch)) {
+            return Ok(());
         }
+        self.query(redis::cmd("CLUSTER").arg("SET-CONFIG-EPOCH").arg(epoch))
     }
 
-    pub fn nodes(&self) -> Vec<Node> {
-        if let Some(conn) = self.conn.as_ref() {
-            let info: String = redis::cmd("CLUSTER").arg("NODES").query(conn).unwrap();
-            // let infos: Vec<String> = info.split("\n").map(|x| x.to_string()).collect();
-            let mut nodes: Vec<Node> = Vec::new();
-            for mut info in info.lines() {
-                let kv: Vec<String> = info.split(' ').map(|x| x.to_string()).collect();
-                if kv.len() < 8 {
-                    return vec![];
-                }
-                let mut slots = vec![];
-                let mut migrating = HashMap::new();
-                let mut importing = HashMap::new();
-                let addr = kv[1].split('@').next().expect("must contain addr");
-                let mut node = Node::new(addr.as_bytes()).unwrap();
-                if kv[2].contains("master") {
-                    node.set_role(Role::Master);
-                } else {
-                    node.set_role(Role::Slave);
-                }
-                if kv[2].contains("self") {
-                    node.myself = Some(true);
-                }
-                if kv[3] != "-" {
-                    node.slaveof = Some(kv[3].clone());
-                }
-                for content in &kv[8..] {
-                    if content.contains("->-") {
-                        let migrate = &content[1..content.len() - 1];
-                        let mut scope: Vec<&str> = migrate.split("->-").collect();
-                        let slot = scope[0].to_string().parse::<usize>().unwrap();
-                        let nodeid = scope[1];
-                        migrating.insert(slot, nodeid.to_string());
-                    } else if content.contains("-<-") {
-                        // trim [ ]
-                        let migrate = &content[1..content.len() - 1];
-                        let mut scope: Vec<&str> = migrate.split("-<-").collect();
-                        let slot = scope[0].to_string().parse::<usize>().unwrap();
-                        let nodeid = scope[1];
-                        importing.insert(slot, nodeid.to_string());
-                    } else {
-                        let mut scope: Vec<&str> = content.split('-').collect();
-                        let start = scope[0].to_string().parse::<usize>().unwrap();
-                        slots.push(start);
-                        if scope.len() == 2 {
-                            let end = scope[1].to_string().parse::<usize>().unwrap();
-                            for i in start + 1..=end {
-                                slots.push(i);
-                            }
-                        }
-                    }
-                }
-                node.migrating = migrating;
-                node.importing = importing;
-                node.slots = RefCell::new(slots);
-                node.name = kv[0].clone();
-                self.nodes
-                    .borrow_mut()
-                    .insert(node.name.clone(), node.clone());
-                nodes.push(node);
+    pub fn entries(&self) -> AsResult<Vec<NodeEntry>> {
+        let info: String = self.query(redis::cmd("CLUSTER").arg("NODES"))?;
+        parser::parse_nodes(&info)
+    }
+
+    pub fn nodes(&self) -> AsResult<Vec<Node>> {
+        let mut nodes: Vec<Node> = Vec::new();
+        for entry in self.entries()? {
+            let mut node = Node::from_addr(entry.addr.clone());
+            if entry.has(&Flag::Master) {
+                node.set_role(Role::Master);
+            } else {
+                node.set_role(Role::Slave);
+            }
+            if entry.has(&Flag::Myself) {
+                node.myself = Some(true);
             }
-            return nodes;
+            node.slaveof = entry.master_id.clone();
+            node.slots = RefCell::new(entry.slot_list());
+            node.migrating = entry.migrating;
+            node.importing = entry.importing;
+            node.name = entry.id;
+            self.nodes
+                .borrow_mut()
+                .insert(node.name.clone(), node.clone());
+            nodes.push(node);
         }
-        vec![]
+        Ok(nodes)
     }
 
-    pub fn meet(&self, ip: &str, port: &str) {
-        if let Some(conn) = self.conn.as_ref() {
-            let _: () = redis::cmd("CLUSTER")
-                .arg("MEET")
-                .arg(ip)
-                .arg(port)
-                .query(conn)
-                .unwrap();
+    pub fn meet(&self, node: &Node) -> AsResult<()> {
+        let addr = node.address();
+        let ip = addr.ip()?;
+        if self.dry_run(format!("CLUSTER MEET {} {}", ip, addr.port)) {
+            return Ok(());
         }
+        self.query(redis::cmd("CLUSTER").arg("MEET").arg(ip).arg(addr.port))
     }
 
     pub fn slots(&self) -> Vec<usize> {
@@ -439,74 +618,424 @@ impl Node {
         self.role == Some(Role::Master)
     }
 
-    pub fn forget(&self, node: &Node) {
-        if let Some(conn) = self.conn.as_ref() {
-            let _: () = redis::cmd("CLUSTER")
-                .arg("FORGET")
-                .arg(&node.name)
-                .query(conn)
-                .unwrap();
+    pub fn forget(&self, node: &Node) -> AsResult<()> {
+        if self.dry_run(format!("CLUSTER FORGET {}", node.name)) {
+            return Ok(());
         }
+        self.query(redis::cmd("CLUSTER").arg("FORGET").arg(&node.name))
     }
 
-    pub fn setslot(&self, state: &str, nodeid: String, slot: usize) {
-        if let Some(conn) = self.conn.as_ref() {
-            let _: () = redis::cmd("CLUSTER")
+    // drops every other node from this node's table so it stops gossiping them back
+    pub fn reset(&self) -> AsResult<()> {
+        if self.dry_run("CLUSTER RESET SOFT".to_string()) {
+            return Ok(());
+        }
+        self.query(redis::cmd("CLUSTER").arg("RESET").arg("SOFT"))
+    }
+
+    pub fn failover(&self, option: Option<&str>) -> AsResult<()> {
+        let mut cmd = redis::cmd("CLUSTER");
+        cmd.arg("FAILOVER");
+        if let Some(option) = option {
+            cmd.arg(option);
+        }
+        if self.dry_run(format!("CLUSTER FAILOVER {}", option.unwrap_or_default())) {
+            return Ok(());
+        }
+        self.query(&cmd)
+    }
+
+    pub fn setslot(&self, state: &str, nodeid: String, slot: usize) -> AsResult<()> {
+        if self.dry_run(format!("CLUSTER SETSLOT {} {} {}", slot, state, nodeid)) {
+            return Ok(());
+        }
+        self.query(
+            redis::cmd("CLUSTER")
                 .arg("SETSLOT")
                 .arg(slot)
                 .arg(state)
-                .arg(&*nodeid)
-                .query(conn)
-                .unwrap();
+                .arg(&*nodeid),
+        )
+    }
+
+    pub fn setslot_stable(&self, slot: usize) -> AsResult<()> {
+        if self.dry_run(format!("CLUSTER SETSLOT {} STABLE", slot)) {
+            return Ok(());
         }
+        self.query(redis::cmd("CLUSTER").arg("SETSLOT").arg(slot).arg("STABLE"))
     }
-    fn setslot_stable(&self, slot: usize) {
-        if let Some(conn) = self.conn.as_ref() {
-            let _: () = redis::cmd("CLUSTER")
-                .arg("SETSLOT")
-                .arg(slot)
-                .arg("STABLE")
-                .query(conn)
-                .unwrap();
+
+    // estimated bytes of every slot from the memory usage of up to sample keys of it,
+    // each step is one pipeline for all the slots
+    pub fn slot_sizes(&self, slots: &[usize], sample: usize) -> AsResult<Vec<u64>> {
+        let counts = self.countkeysinslots(slots)?;
+        let used: Vec<(usize, usize)> = slots
+            .iter()
+            .zip(&counts)
+            .filter(|(_, count)| **count > 0)
+            .map(|(slot, count)| (*slot, *count))
+            .collect();
+        if used.is_empty() {
+            return Ok(vec![0; slots.len()]);
+        }
+        let mut pipe = redis::pipe();
+        for (slot, count) in &used {
+            pipe.cmd("CLUSTER")
+                .arg("GETKEYSINSLOT")
+                .arg(*slot)
+                .arg(sample.min(*count));
+        }
+        let keys: Vec<Vec<Vec<u8>>> = self.query_pipe(&pipe)?;
+        let mut pipe = redis::pipe();
+        for key in keys.iter().flatten() {
+            pipe.cmd("MEMORY").arg("USAGE").arg(key);
         }
+        let usages: Vec<Option<u64>> = if keys.iter().all(|x| x.is_empty()) {
+            Vec::new()
+        } else {
+            self.query_pipe(&pipe)?
+        };
+        let mut usages = usages.into_iter();
+        let mut sizes: HashMap<usize, u64> = HashMap::new();
+        for ((slot, count), keys) in used.iter().zip(&keys) {
+            let sampled: Vec<Option<u64>> = usages.by_ref().take(keys.len()).collect();
+            sizes.insert(*slot, estimate(*count, &sampled));
+        }
+        Ok(slots
+            .iter()
+            .map(|slot| sizes.get(slot).cloned().unwrap_or(0))
+            .collect())
     }
 
-    fn keysinslot(&self, slot: usize) -> Option<Vec<String>> {
-        if let Some(conn) = self.conn.as_ref() {
-            let result: Vec<String> = redis::cmd("CLUSTER")
+    pub fn readonly(&self) -> AsResult<()> {
+        self.query(&redis::cmd("READONLY"))
+    }
+
+    pub fn scan(&self, cursor: u64, count: usize) -> AsResult<(u64, Vec<Vec<u8>>)> {
+        self.query(redis::cmd("SCAN").arg(cursor).arg("COUNT").arg(count))
+    }
+
+    pub fn key_types(&self, keys: &[Vec<u8>]) -> AsResult<Vec<String>> {
+        let mut pipe = redis::pipe();
+        for key in keys {
+            pipe.cmd("TYPE").arg(key);
+        }
+        self.query_pipe(&pipe)
+    }
+
+    // memory usage estimated from samples elements (0 for all of them) and element
+    // count (string length for strings) of every key
+    pub fn key_sizes(
+        &self,
+        keys: &[(Vec<u8>, String)],
+        samples: usize,
+    ) -> AsResult<Vec<(u64, Option<u64>)>> {
+        let mut pipe = redis::pipe();
+        for (key, _) in keys {
+            pipe.cmd("MEMORY")
+                .arg("USAGE")
+                .arg(key)
+                .arg("SAMPLES")
+                .arg(samples);
+        }
+        let usages: Vec<Option<u64>> = self.query_pipe(&pipe)?;
+        let mut pipe = redis::pipe();
+        for (key, kind) in keys {
+            if let Some(cmd) = length_command(kind) {
+                pipe.cmd(cmd).arg(key);
+            }
+        }
+        let mut lengths: Vec<u64> = self.query_pipe(&pipe)?;
+        lengths.reverse();
+        Ok(keys
+            .iter()
+            .zip(usages)
+            .map(|((_, kind), usage)| {
+                let length = length_command(kind).and_then(|_| lengths.pop());
+                (usage.unwrap_or(0), length)
+            })
+            .collect())
+    }
+
+    pub fn object_freqs(&self, keys: &[Vec<u8>]) -> AsResult<Vec<Option<u64>>> {
+        let mut pipe = redis::pipe();
+        for key in keys {
+            pipe.cmd("OBJECT").arg("FREQ").arg(key);
+        }
+        self.query_pipe(&pipe)
+    }
+
+    // feeds every MONITOR line to f until duration elapses, on a connection of its own
+    pub fn monitor<F: FnMut(&str)>(&self, duration: time::Duration, mut f: F) -> AsResult<()> {
+        let mut conn = self.open()?;
+        let err = |err: RedisError| Error::from_redis(self.addr(), err);
+        let _: () = redis::cmd("MONITOR").query(&mut conn).map_err(err)?;
+        let deadline = time::Instant::now() + duration;
+        loop {
+            let now = time::Instant::now();
+            if now >= deadline {
+                return Ok(());
+            }
+            conn.set_read_timeout(Some(deadline - now)).map_err(err)?;
+            match conn.recv_response() {
+                Ok(Value::SimpleString(line)) => f(&line),
+                Ok(_) => {}
+                Err(ref e) if e.is_timeout() => return Ok(()),
+                Err(e) => return Err(err(e)),
+            }
+        }
+    }
+
+    fn keysinslot(&self, slot: usize, count: usize) -> AsResult<Vec<Vec<u8>>> {
+        self.query(
+            redis::cmd("CLUSTER")
                 .arg("GETKEYSINSLOT")
                 .arg(slot)
-                .arg(100)
-                .query(conn)
-                .unwrap();
-            if result.is_empty() {
-                return Some(result);
-            }
+                .arg(count),
+        )
+    }
+
+    pub fn countkeysinslots(&self, slots: &[usize]) -> AsResult<Vec<usize>> {
+        let mut pipe = redis::pipe();
+        for slot in slots {
+            pipe.cmd("CLUSTER").arg("COUNTKEYSINSLOT").arg(*slot);
         }
-        None
+        self.query_pipe(&pipe)
+    }
+
+    fn countkeysinslot(&self, slot: usize) -> AsResult<usize> {
+        self.query(redis::cmd("CLUSTER").arg("COUNTKEYSINSLOT").arg(slot))
     }
 
-    fn migrate(&self, dstip: &str, dstport: &str, key: Vec<String>) {
-        println!("migrate keys {:?}", key);
-        if let Some(conn) = self.conn.as_ref() {
-            let _: () = redis::cmd("MIGRATE")
-                .arg(dstip)
-                .arg(dstport)
+    fn migrate(&self, dst: &Node, slot: usize, keys: &[Vec<u8>], opts: &Migration) -> AsResult<()> {
+        println!(
+            "migrate {} keys of slot {} from {} to {}",
+            keys.len(),
+            slot,
+            self.addr(),
+            dst.addr()
+        );
+        let mut replace = false;
+        let mut retries = 0;
+        loop {
+            let mut cmd = redis::cmd("MIGRATE");
+            cmd.arg(&dst.addr.host)
+                .arg(dst.addr.port)
                 .arg("")
-                .arg("0")
-                .arg(5000)
-                .arg("KEYS")
-                .arg(key)
-                .query(conn)
-                .unwrap();
+                .arg(0)
+                .arg(opts.timeout);
+            if replace {
+                cmd.arg("REPLACE");
+            }
+            if let Some(auth) = auth() {
+                match auth.user {
+                    Some(ref user) => cmd.arg("AUTH2").arg(user).arg(&auth.password),
+                    None => cmd.arg("AUTH").arg(&auth.password),
+                };
+            }
+            let err = match self.query::<()>(cmd.arg("KEYS").arg(keys)) {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+            if err.is_reply("BUSYKEY") {
+                if !opts.replace {
+                    return Err(Error::State(format!(
+                        "{}, use --migrate-replace to overwrite keys of {}",
+                        err,
+                        dst.addr()
+                    )));
+                }
+                if !replace {
+                    eprintln!("{}, retry slot {} with REPLACE", err, slot);
+                    replace = true;
+                    continue;
+                }
+            }
+            if err.is_reply("IOERR") && retries < opts.retries {
+                retries += 1;
+                eprintln!(
+                    "{}, retry slot {} ({}/{})",
+                    err, slot, retries, opts.retries
+                );
+                thread::sleep(time::Duration::from_millis(100 * retries as u64));
+                continue;
+            }
+            return Err(err);
+        }
+    }
+
+    fn node_by_id(&self, nodeid: &str) -> AsResult<Node> {
+        if !self.nodes.borrow().contains_key(nodeid) {
+            self.nodes()?;
+        }
+        self.nodes
+            .borrow()
+            .get(nodeid)
+            .cloned()
+            .ok_or_else(|| Error::State(format!("node {} is unknown to {}", nodeid, self.addr())))
+    }
+
+    fn open(&self) -> AsResult<Connection> {
+        if self.addr.host.is_empty() {
+            return Err(Error::Connect(
+                self.addr(),
+                "node has no address".to_string(),
+            ));
+        }
+        let addr = match tls() {
+            Some(tls) => ConnectionAddr::TcpTls {
+                host: self
+                    .addr
+                    .hostname
+                    .clone()
+                    .unwrap_or_else(|| self.addr.host.clone()),
+                port: self.addr.port,
+                insecure: tls.insecure,
+                tls_params: None,
+            },
+            None => ConnectionAddr::Tcp(self.addr.host.clone(), self.addr.port),
+        };
+        let mut redis_info = RedisConnectionInfo::default();
+        if let Some(auth) = auth() {
+            redis_info.username = auth.user.clone();
+            redis_info.password = Some(auth.password.clone());
+        }
+        let info = ConnectionInfo {
+            addr,
+            redis: redis_info,
+        };
+        let connect_err = |err: RedisError| Error::Connect(self.addr(), err.to_string());
+        let client = match tls() {
+            Some(tls) => redis::Client::build_with_tls(info, tls.certificates()),
+            None => redis::Client::open(info),
+        }
+        .map_err(connect_err)?;
+        client.get_connection().map_err(connect_err)
+    }
+
+    fn query<T: FromRedisValue>(&self, cmd: &redis::Cmd) -> AsResult<T> {
+        self.with_conn(|conn| cmd.query(conn))
+    }
+
+    fn query_pipe<T: FromRedisValue>(&self, pipe: &redis::Pipeline) -> AsResult<T> {
+        self.with_conn(|conn| pipe.query(conn))
+    }
+
+    fn with_conn<T, F>(&self, f: F) -> AsResult<T>
+    where
+        F: FnOnce(&mut Connection) -> redis::RedisResult<T>,
+    {
+        let mut conn = self.conn.borrow_mut();
+        if conn.is_none() {
+            *conn = Some(self.open()?);
+        }
+        let result = match *conn {
+            Some(ref mut conn) => f(conn).map_err(|err| Error::from_redis(self.addr(), err)),
+            None => unreachable!(),
+        };
+        if let Err(Error::Connect(..)) = result {
+            *conn = None;
+        }
+        result
+    }
+
+    fn dry_run(&self, command: String) -> bool {
+        if !is_dry_run() {
+            return false;
         }
+        println!("[dry-run] {} {}", self.addr(), command);
+        true
+    }
+}
+
+fn length_command(kind: &str) -> Option<&'static str> {
+    match kind {
+        "string" => Some("STRLEN"),
+        "hash" => Some("HLEN"),
+        "list" => Some("LLEN"),
+        "set" => Some("SCARD"),
+        "zset" => Some("ZCARD"),
+        "stream" => Some("XLEN"),
+        _ => None,
     }
 }
 
+fn parse_info(info: &str) -> HashMap<String, String> {
+    let mut node_infos = HashMap::new();
+    for line in info.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        let kv: Vec<&str> = line.splitn(2, ':').collect();
+        if kv.len() == 2 {
+            node_infos.insert(kv[0].to_string(), kv[1].to_string());
+        }
+    }
+    node_infos
+}
+
 #[derive(Debug)]
 pub enum Error {
     BadAddr,
     BadCluster,
+    Connect(String, String),
+    Protocol(String),
+    Reply(String, String),
+    State(String),
+    Io(io::Error),
+}
+
+impl Error {
+    pub fn is_reply(&self, code: &str) -> bool {
+        match self {
+            Error::Reply(_, msg) => msg.starts_with(code),
+            _ => false,
+        }
+    }
+
+    fn from_redis(addr: String, err: RedisError) -> Error {
+        match err.kind() {
+            ErrorKind::IoError => Error::Connect(addr, err.to_string()),
+            ErrorKind::TypeError => Error::Protocol(format!("{}: {}", addr, err)),
+            _ => Error::Reply(addr, err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BadAddr => write!(f, "bad address, must be formatted as ip:port"),
+            Error::BadCluster => write!(f, "bad cluster, need at least 3 masters"),
+            Error::Connect(addr, msg) => write!(f, "connect to {} fail: {}", addr, msg),
+            Error::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            Error::Reply(addr, msg) => write!(f, "{} replied error: {}", addr, msg),
+            Error::State(msg) => write!(f, "invalid cluster state: {}", msg),
+            Error::Io(err) => write!(f, "io error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
 }
 
 pub type AsResult<T> = result::Result<T, Error>;
+
+// bytes of count keys from the memory usage of a sample of them, keys gone since are skipped
+fn estimate(count: usize, usages: &[Option<u64>]) -> u64 {
+    let (total, sampled) = usages
+        .iter()
+        .flatten()
+        .fold((0u64, 0u64), |(total, sampled), usage| {
+            (total + usage, sampled + 1)
+        });
+    if sampled == 0 {
+        return 0;
+    }
+    total * count as u64 / sampled
+}
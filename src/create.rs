@@ -1,21 +1,22 @@
-use cluster::{Cluster, Error, Node};
+use cluster::{AsResult, Cluster, Error, Node};
 use std::collections::HashMap;
-use util;
+use util::{self, CLUSTER_SLOTS};
 
 #[test]
 fn test_cluster() {
-    let mut addrs: Vec<&str> = Vec::new();
-    addrs.push("127.0.0.1:9999");
-    addrs.push("127.0.0.2:8888");
-    addrs.push("127.0.0.3:8888");
-    addrs.push("127.0.0.4:8888");
-    addrs.push("127.0.0.1:8889");
-    addrs.push("127.0.0.2:8889");
-    addrs.push("127.0.0.3:8889");
-    addrs.push("127.0.0.4:8889");
-
-    let mut cluster = Create::new(addrs, 4, 4).unwrap();
-    cluster.init_slots();
+    let addrs: Vec<&str> = vec![
+        "127.0.0.1:9999",
+        "127.0.0.2:8888",
+        "127.0.0.3:8888",
+        "127.0.0.4:8888",
+        "127.0.0.1:8889",
+        "127.0.0.2:8889",
+        "127.0.0.3:8889",
+        "127.0.0.4:8889",
+    ];
+
+    let mut cluster = Create::new(addrs, 4, 1).unwrap();
+    cluster.init_slots(false).unwrap();
     assert_eq!(cluster.master.len(), 4);
     assert_eq!(cluster.slots.len(), 4);
     assert_eq!(cluster.slave.len(), 4);
@@ -51,6 +52,59 @@ fn test_spread() {
     println!("{:?}", target.pop());
 }
 
+#[test]
+fn test_zone_locations() {
+    let nodes = vec![
+        Node::new(b"127.0.0.1:7000@zone=a").unwrap(),
+        Node::new(b"127.0.0.1:7001@zone=b").unwrap(),
+    ];
+    assert_eq!(locations(&nodes).unwrap(), vec!["a", "b"]);
+    let nodes = vec![
+        Node::new(b"127.0.0.1:7000").unwrap(),
+        Node::new(b"127.0.0.2:7001").unwrap(),
+    ];
+    assert_eq!(locations(&nodes).unwrap(), vec!["127.0.0.1", "127.0.0.2"]);
+    let nodes = vec![
+        Node::new(b"127.0.0.1:7000@zone=a").unwrap(),
+        Node::new(b"127.0.0.1:7001").unwrap(),
+    ];
+    assert!(locations(&nodes).is_err());
+}
+
+#[test]
+fn test_place_replicas() {
+    let masters = vec!["a", "b", "c"];
+    let slaves = vec!["a", "b", "c", "a", "b", "c"];
+    let placed = place_replicas(&masters, &slaves, false).unwrap();
+    for (slave, master) in placed.iter().enumerate() {
+        assert_ne!(slaves[slave], masters[*master]);
+    }
+    for master in 0..masters.len() {
+        let hosts: Vec<&str> = placed
+            .iter()
+            .enumerate()
+            .filter(|x| *x.1 == master)
+            .map(|x| slaves[x.0])
+            .collect();
+        assert_eq!(hosts.len(), 2);
+        assert_ne!(hosts[0], hosts[1]);
+    }
+}
+
+#[test]
+fn test_place_replicas_impossible() {
+    let masters = vec!["a", "a", "a"];
+    let slaves = vec!["a", "a", "a"];
+    assert!(place_replicas(&masters, &slaves, false).is_err());
+    assert_eq!(
+        place_replicas(&masters, &slaves, true).unwrap(),
+        vec![0, 1, 2]
+    );
+    let masters = vec!["a", "b", "c"];
+    let slaves = vec!["a", "a", "a"];
+    assert!(place_replicas(&masters, &slaves, false).is_err());
+}
+
 pub struct Create {
     pub cluster: Cluster,
     master_count: usize,
@@ -60,10 +114,7 @@ pub struct Create {
     slave: Vec<Node>,
 }
 
-const CLUSTER_SLOTS: usize = 16384;
-
 impl Create {
-
     pub fn new(
         addrs: Vec<&str>,
         mut master_count: usize,
@@ -71,8 +122,8 @@ impl Create {
     ) -> Result<Create, Error> {
         let mut nodes = Vec::new();
         for n in addrs.into_iter() {
-            let mut node = Node::new(n.as_bytes()).unwrap();
-            node.connect();
+            let mut node = Node::new(n.as_bytes())?;
+            node.connect()?;
             nodes.push(node);
         }
         let mut create = Create {
@@ -87,92 +138,97 @@ impl Create {
             master_count = create.cluster.len() / (slave_count + 1);
         }
         if master_count < 3 {
-            Err(Error::BadCluster)
-        } else {
-            create.master_count = master_count;
-            create.slave_count = slave_count;
-            Ok(create)
+            return Err(Error::BadCluster);
+        }
+        let needed = master_count * (slave_count + 1);
+        if needed > create.cluster.len() {
+            return Err(Error::State(format!(
+                "{} masters with {} replicas each need {} nodes, got {}",
+                master_count,
+                slave_count,
+                needed,
+                create.cluster.len()
+            )));
         }
+        create.master_count = master_count;
+        create.slave_count = slave_count;
+        Ok(create)
     }
 
-    pub fn init_slots(&mut self) {
+    pub fn init_slots(&mut self, allow_same_host: bool) -> AsResult<()> {
+        let locations = locations(&self.cluster.nodes)?;
         let slaves = {
-            let mut ips = HashMap::new();
-            for n in &self.cluster.nodes {
-                let key = &*n.ip;
-                ips.entry(key).or_insert_with(|| vec![]).push(n.clone());
+            let mut groups = HashMap::new();
+            for (n, location) in self.cluster.nodes.iter().zip(&locations) {
+                groups
+                    .entry(&**location)
+                    .or_insert_with(std::vec::Vec::new)
+                    .push(n.clone());
             }
-            self.master = spread(&mut ips, self.master_count).expect("spread master err");
+            let short = || Error::State("not enough nodes to spread".to_string());
+            self.master = spread(&mut groups, self.master_count).ok_or_else(short)?;
             println!("create redis cluster");
             println!("distribute master");
             for node in &self.master {
                 println!("master: {:?}", node);
             }
             self.slots = slpit_slots(CLUSTER_SLOTS, self.master_count).unwrap();
-            spread(&mut ips, self.cluster.len() - self.master_count).unwrap()
+            spread(&mut groups, self.cluster.len() - self.master_count).ok_or_else(short)?
         };
-        self.distribute_slave(slaves);
+        self.distribute_slave(slaves, allow_same_host)?;
         println!("distributie slave");
         for node in &self.slave {
             println!("slave: {:?}", node);
         }
+        Ok(())
     }
 
-    pub fn add_slots(&mut self) {
+    pub fn add_slots(&mut self) -> AsResult<()> {
         for node in &self.master {
             let chunk = &self.slots.pop().unwrap();
-            node.add_slots(&(chunk.0..chunk.1).collect::<Vec<usize>>());
+            node.add_slots(&(chunk.0..chunk.1).collect::<Vec<usize>>())?;
         }
+        Ok(())
     }
 
-    pub fn set_config_epoch(&self) {
+    pub fn set_config_epoch(&self) -> AsResult<()> {
         let epoch = 1;
         for node in &self.master {
-            node.set_config_epoch(epoch);
+            node.set_config_epoch(epoch)?;
         }
+        Ok(())
     }
 
-    pub fn join_cluster(&mut self) {
+    pub fn join_cluster(&mut self) -> AsResult<()> {
         if self.cluster.len() == 0 {
-            return;
+            return Ok(());
         }
         let first_node = self.cluster.nodes.pop().unwrap();
         for node in &self.cluster.nodes {
-            first_node.meet(&*node.ip, &*node.port);
+            first_node.meet(node)?;
         }
+        Ok(())
     }
 
-    pub fn set_slave(&self) -> Result<(), Error> {
+    pub fn set_slave(&self) -> AsResult<()> {
         for node in &self.slave {
-            let _: () = node.set_slave();
+            node.set_slave()?;
         }
         Ok(())
     }
 
-    fn distribute_slave(&mut self, slaves: Vec<Node>) {
-        let mut inuse = HashMap::new();
-        loop {
-            for master in &self.master {
-                for slave in &slaves {
-                    // if master.ip == slave.ip {
-                    //     continue;
-                    // }
-                    let mut key = slave.ip.clone() + ":" + &slave.port;
-                    if inuse.contains_key(&key) {
-                        continue;
-                    }
-                    inuse.insert(key, slave);
-                    let mut s =
-                        Node::new((slave.ip.clone() + ":" + &*slave.port).as_bytes()).unwrap();
-                    s.slaveof = Some(master.name.clone());
-                    self.slave.push(s);
-                    break;
-                }
-            }
-            if self.slave.len() >= slaves.len() {
-                break;
-            }
+    fn distribute_slave(&mut self, slaves: Vec<Node>, allow_same_host: bool) -> AsResult<()> {
+        let masters = locations(&self.master)?;
+        let masters: Vec<&str> = masters.iter().map(|x| &**x).collect();
+        let hosts = locations(&slaves)?;
+        let hosts: Vec<&str> = hosts.iter().map(|x| &**x).collect();
+        let placed = place_replicas(&masters, &hosts, allow_same_host).map_err(Error::State)?;
+        for (slave, master) in slaves.iter().zip(placed) {
+            let mut s = Node::from_addr(slave.address());
+            s.slaveof = Some(self.master[master].name.clone());
+            self.slave.push(s);
         }
+        Ok(())
     }
 
     pub fn consistent(&self) -> bool {
@@ -191,6 +247,84 @@ pub fn slpit_slots(n: usize, m: usize) -> Option<Vec<Chunk>> {
     Some(res)
 }
 
+// the zone of every node when zones are labeled, its host otherwise
+fn locations(nodes: &[Node]) -> AsResult<Vec<String>> {
+    let zones: Vec<Option<String>> = nodes.iter().map(|x| x.zone()).collect();
+    if zones.iter().all(|x| x.is_none()) {
+        return Ok(nodes.iter().map(|x| x.address().host).collect());
+    }
+    nodes
+        .iter()
+        .zip(zones)
+        .map(|(node, zone)| {
+            zone.ok_or_else(|| Error::State(format!("node {} has no zone label", node.addr())))
+        })
+        .collect()
+}
+
+// assigns every replica (by location) to a master index, round robin over the masters.
+// a replica never shares the location of its master unless allow_same_host is set, and
+// the replicas of one master are spread over distinct locations whenever possible.
+fn place_replicas(
+    masters: &[&str],
+    slaves: &[&str],
+    allow_same_host: bool,
+) -> Result<Vec<usize>, String> {
+    let mut placed: Vec<Option<usize>> = vec![None; slaves.len()];
+    let mut left = slaves.len();
+    while left > 0 {
+        for (idx, host) in masters.iter().enumerate() {
+            if left == 0 {
+                break;
+            }
+            let used: Vec<&str> = placed
+                .iter()
+                .enumerate()
+                .filter(|x| *x.1 == Some(idx))
+                .map(|x| slaves[x.0])
+                .collect();
+            let free = |slave: &usize| placed[*slave].is_none();
+            let remain = |slave: &usize| {
+                (0..slaves.len())
+                    .filter(|x| free(x) && slaves[*x] == slaves[*slave])
+                    .count()
+            };
+            let pick = (0..slaves.len())
+                .filter(|x| free(x) && slaves[*x] != *host && !used.contains(&slaves[*x]))
+                .max_by_key(|x| (remain(x), slaves.len() - x));
+            let pick = match pick {
+                Some(pick) => pick,
+                None => {
+                    let other = (0..slaves.len()).find(|x| free(x) && slaves[*x] != *host);
+                    if let Some(other) = other {
+                        eprintln!(
+                            "warning: master in {} gets a second replica in {}",
+                            host, slaves[other]
+                        );
+                        other
+                    } else if allow_same_host {
+                        let same = (0..slaves.len()).find(free).unwrap();
+                        eprintln!(
+                            "warning: master on host {} gets a replica on the same host",
+                            host
+                        );
+                        same
+                    } else {
+                        return Err(format!(
+                            "no replica left outside {} for its master, \
+                             add nodes on other hosts or zones or pass --allow-same-host",
+                            host
+                        ));
+                    }
+                }
+            };
+            placed[pick] = Some(idx);
+            left -= 1;
+        }
+    }
+    Ok(placed.into_iter().map(|x| x.unwrap()).collect())
+}
+
 #[derive(Debug)]
 pub struct Chunk(usize, usize);
 
@@ -217,9 +351,9 @@ fn spread(nodes: &mut HashMap<&str, Vec<Node>>, n: usize) -> Option<Vec<Node>> {
             if target.len() >= n {
                 return Some(target);
             }
-            let node = v.pop().unwrap();
-            // println!("{:?} {} {}", &node, target.len(), n);
-            target.push(node);
+            if let Some(node) = v.pop() {
+                target.push(node);
+            }
         }
     }
     // let target = nodes
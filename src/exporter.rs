@@ -0,0 +1,465 @@
+use cluster::{AsResult, Node};
+use parser::{Flag, NodeEntry};
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+fn info(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_render() {
+    let entries = ::parser::parse_nodes(
+        "\
+aaaa 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-5460 [5461->-bbbb]
+bbbb 127.0.0.1:7001@17001 master,fail - 0 0 2 connected 5461-16383
+cccc 127.0.0.1:7002@17002 slave aaaa 0 0 1 connected
+",
+    )
+    .unwrap();
+    let infos = vec![
+        Some(info(&[
+            ("used_memory", "1024"),
+            ("instantaneous_ops_per_sec", "7"),
+            ("master_repl_offset", "1500"),
+        ])),
+        None,
+        Some(info(&[
+            ("master_link_status", "up"),
+            ("slave_repl_offset", "1200"),
+        ])),
+    ];
+    let nodes: Vec<Sample> = entries
+        .into_iter()
+        .zip(infos)
+        .map(|(entry, info)| Sample { entry, info })
+        .collect();
+    let cluster = info(&[
+        ("cluster_state", "fail"),
+        ("cluster_slots_assigned", "16384"),
+        ("cluster_slots_fail", "10923"),
+        ("cluster_known_nodes", "3"),
+    ]);
+    let text = render(Some(&cluster), &nodes, 0.5);
+    for line in &[
+        "rckit_up 1",
+        "rckit_cluster_state 0",
+        "rckit_cluster_slots{state=\"assigned\"} 16384",
+        "rckit_cluster_slots{state=\"fail\"} 10923",
+        "rckit_cluster_known_nodes 3",
+        "rckit_node_info{addr=\"127.0.0.1:7002\",id=\"cccc\",role=\"slave\",master_id=\"aaaa\"} 1",
+        "rckit_node_up{addr=\"127.0.0.1:7001\",id=\"bbbb\"} 0",
+        "rckit_node_failing{addr=\"127.0.0.1:7001\",id=\"bbbb\"} 1",
+        "rckit_node_slots{addr=\"127.0.0.1:7000\",id=\"aaaa\"} 5461",
+        "rckit_node_open_slots{addr=\"127.0.0.1:7000\",id=\"aaaa\",state=\"migrating\"} 1",
+        "rckit_node_used_memory_bytes{addr=\"127.0.0.1:7000\",id=\"aaaa\"} 1024",
+        "rckit_node_ops_per_sec{addr=\"127.0.0.1:7000\",id=\"aaaa\"} 7",
+        "rckit_node_master_link_up{addr=\"127.0.0.1:7002\",id=\"cccc\"} 1",
+        "rckit_node_replication_lag_bytes{addr=\"127.0.0.1:7002\",id=\"cccc\"} 300",
+    ] {
+        assert!(text.lines().any(|x| x == *line), "missing {}", line);
+    }
+    assert!(!text.contains("rckit_node_used_memory_bytes{addr=\"127.0.0.1:7001\""));
+
+    let down = render(None, &[], 0.5);
+    assert!(down.lines().any(|x| x == "rckit_up 0"));
+    assert!(!down.contains("rckit_cluster_state"));
+}
+
+#[test]
+fn test_render_open_slots() {
+    let mut entries = ::parser::parse_nodes(
+        "\
+aaaa 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-8191
+bbbb 127.0.0.1:7001@17001 master - 0 0 2 connected 8192-16383
+",
+    )
+    .unwrap();
+    let own = ::parser::parse_nodes(
+        "\
+aaaa 127.0.0.1:7000@17000 master - 0 0 1 connected 0-8191
+bbbb 127.0.0.1:7001@17001 myself,master - 0 0 2 connected 8192-16383 [8192->-aaaa]
+",
+    )
+    .unwrap();
+    entries[1].open_slots_from(own);
+    let nodes: Vec<Sample> = entries
+        .into_iter()
+        .map(|entry| Sample { entry, info: None })
+        .collect();
+    let text = render(Some(&info(&[("cluster_state", "ok")])), &nodes, 0.5);
+    for line in &[
+        "rckit_node_open_slots{addr=\"127.0.0.1:7000\",id=\"aaaa\",state=\"migrating\"} 0",
+        "rckit_node_open_slots{addr=\"127.0.0.1:7001\",id=\"bbbb\",state=\"migrating\"} 1",
+    ] {
+        assert!(text.lines().any(|x| x == *line), "missing {}", line);
+    }
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+}
+
+pub struct Sample {
+    pub entry: NodeEntry,
+    pub info: Option<HashMap<String, String>>,
+}
+
+pub fn serve(listen: &str, seed: &str, interval: Duration) -> AsResult<()> {
+    let listener = TcpListener::bind(listen)?;
+    let mut seeds = vec![seed.to_string()];
+    let metrics = Arc::new(Mutex::new(collect(&mut seeds)));
+    let shared = metrics.clone();
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let text = collect(&mut seeds);
+        *shared.lock().unwrap() = text;
+    });
+    println!("serve metrics of {} on http://{}/metrics", seed, listen);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("serve scrape fail: {}", err);
+                continue;
+            }
+        };
+        // a slow client must not hold up the other scrapes
+        let metrics = metrics.clone();
+        thread::spawn(move || {
+            if let Err(err) = respond(stream, &metrics) {
+                eprintln!("serve scrape fail: {}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, metrics: &Mutex<String>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            metrics.lock().unwrap().clone(),
+        ),
+        ("GET", "/") => (
+            "200 OK",
+            "text/html",
+            "<html><body><a href=\"/metrics\">metrics</a></body></html>\n".to_string(),
+        ),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn collect(seeds: &mut Vec<String>) -> String {
+    let start = Instant::now();
+    match snapshot(seeds) {
+        Some((cluster, nodes)) => render(Some(&cluster), &nodes, start.elapsed().as_secs_f64()),
+        None => render(None, &[], start.elapsed().as_secs_f64()),
+    }
+}
+
+// CLUSTER INFO of the first seed that answers and the INFO of every node it knows,
+// remembering every known node as a seed for the next time
+pub fn snapshot(seeds: &mut Vec<String>) -> Option<(HashMap<String, String>, Vec<Sample>)> {
+    let mut found = None;
+    for seed in seeds.iter() {
+        let result =
+            Node::new(seed.as_bytes()).and_then(|node| Ok((node.info()?, node.entries()?)));
+        match result {
+            Ok(found_cluster) => {
+                found = Some(found_cluster);
+                break;
+            }
+            Err(err) => eprintln!("refresh topology from {} fail: {}", seed, err),
+        }
+    }
+    let (cluster, entries) = found?;
+    seeds.truncate(1);
+    let mut nodes = Vec::new();
+    for mut entry in entries {
+        if entry.addr.host.is_empty() || entry.has(&Flag::Handshake) || entry.has(&Flag::NoAddr) {
+            continue;
+        }
+        seeds.push(entry.addr.to_string());
+        let node = Node::from_addr(entry.addr.clone());
+        // the seed lists the open slots of itself only
+        if !entry.has(&Flag::Myself) {
+            if let Ok(own) = node.entries() {
+                entry.open_slots_from(own);
+            }
+        }
+        let info = node.redis_info().ok();
+        nodes.push(Sample { entry, info });
+    }
+    nodes.sort_by_key(|x| x.entry.addr.to_string());
+    Some((cluster, nodes))
+}
+
+fn render(cluster: Option<&HashMap<String, String>>, nodes: &[Sample], duration: f64) -> String {
+    let mut out = String::new();
+    family(
+        &mut out,
+        "rckit_up",
+        "whether any seed answered CLUSTER INFO and CLUSTER NODES",
+        vec![(String::new(), if cluster.is_some() { 1.0 } else { 0.0 })],
+    );
+    family(
+        &mut out,
+        "rckit_scrape_duration_seconds",
+        "time spent refreshing the topology and the INFO of every node",
+        vec![(String::new(), duration)],
+    );
+    let cluster = match cluster {
+        Some(cluster) => cluster,
+        None => return out,
+    };
+    let number = |info: &HashMap<String, String>, key: &str| {
+        info.get(key).and_then(|x| x.trim().parse::<f64>().ok())
+    };
+    let state = cluster.get("cluster_state").map(|x| x.as_str()) == Some("ok");
+    family(
+        &mut out,
+        "rckit_cluster_state",
+        "1 when cluster_state is ok",
+        vec![(String::new(), if state { 1.0 } else { 0.0 })],
+    );
+    family(
+        &mut out,
+        "rckit_cluster_slots",
+        "slots by state as seen by the seed",
+        ["assigned", "ok", "pfail", "fail"]
+            .iter()
+            .filter_map(|state| {
+                number(cluster, &format!("cluster_slots_{}", state))
+                    .map(|x| (labels(&[("state", state)]), x))
+            })
+            .collect(),
+    );
+    for (name, key, help) in &[
+        (
+            "rckit_cluster_known_nodes",
+            "cluster_known_nodes",
+            "nodes known by the seed",
+        ),
+        (
+            "rckit_cluster_size",
+            "cluster_size",
+            "masters serving at least one slot",
+        ),
+    ] {
+        let samples = number(cluster, key)
+            .map(|x| vec![(String::new(), x)])
+            .unwrap_or_default();
+        family(&mut out, name, help, samples);
+    }
+
+    let node_labels = |node: &Sample| {
+        labels(&[
+            ("addr", &node.entry.addr.to_string()),
+            ("id", &node.entry.id),
+        ])
+    };
+    let offsets: HashMap<&str, f64> = nodes
+        .iter()
+        .filter_map(|x| {
+            let info = x.info.as_ref()?;
+            Some((x.entry.id.as_str(), number(info, "master_repl_offset")?))
+        })
+        .collect();
+    family(
+        &mut out,
+        "rckit_node_info",
+        "role and master of every node",
+        nodes
+            .iter()
+            .map(|x| {
+                let role = if x.entry.has(&Flag::Master) {
+                    "master"
+                } else {
+                    "slave"
+                };
+                let master = x.entry.master_id.clone().unwrap_or_default();
+                let labels = labels(&[
+                    ("addr", &x.entry.addr.to_string()),
+                    ("id", &x.entry.id),
+                    ("role", role),
+                    ("master_id", &master),
+                ]);
+                (labels, 1.0)
+            })
+            .collect(),
+    );
+    family(
+        &mut out,
+        "rckit_node_up",
+        "whether the node answered INFO",
+        nodes
+            .iter()
+            .map(|x| (node_labels(x), if x.info.is_some() { 1.0 } else { 0.0 }))
+            .collect(),
+    );
+    family(
+        &mut out,
+        "rckit_node_failing",
+        "whether the seed flags the node as pfail or fail",
+        nodes
+            .iter()
+            .map(|x| {
+                let failing = x.entry.is_failing();
+                (node_labels(x), if failing { 1.0 } else { 0.0 })
+            })
+            .collect(),
+    );
+    family(
+        &mut out,
+        "rckit_node_slots",
+        "slots served by the node",
+        nodes
+            .iter()
+            .map(|x| (node_labels(x), x.entry.slot_count() as f64))
+            .collect(),
+    );
+    family(
+        &mut out,
+        "rckit_node_open_slots",
+        "slots left migrating or importing on the node",
+        nodes
+            .iter()
+            .flat_map(|x| {
+                vec![
+                    ("migrating", x.entry.migrating.len()),
+                    ("importing", x.entry.importing.len()),
+                ]
+                .into_iter()
+                .map(move |(state, count)| {
+                    let labels = labels(&[
+                        ("addr", &x.entry.addr.to_string()),
+                        ("id", &x.entry.id),
+                        ("state", state),
+                    ]);
+                    (labels, count as f64)
+                })
+            })
+            .collect(),
+    );
+    for (name, key, help) in &[
+        (
+            "rckit_node_used_memory_bytes",
+            "used_memory",
+            "used_memory of INFO",
+        ),
+        (
+            "rckit_node_maxmemory_bytes",
+            "maxmemory",
+            "maxmemory of INFO",
+        ),
+        (
+            "rckit_node_ops_per_sec",
+            "instantaneous_ops_per_sec",
+            "instantaneous_ops_per_sec of INFO",
+        ),
+        (
+            "rckit_node_connected_clients",
+            "connected_clients",
+            "connected_clients of INFO",
+        ),
+    ] {
+        let samples = nodes
+            .iter()
+            .filter_map(|x| Some((node_labels(x), number(x.info.as_ref()?, key)?)))
+            .collect();
+        family(&mut out, name, help, samples);
+    }
+    let replicas: Vec<&Sample> = nodes
+        .iter()
+        .filter(|x| x.entry.master_id.is_some() && x.info.is_some())
+        .collect();
+    family(
+        &mut out,
+        "rckit_node_master_link_up",
+        "whether master_link_status of the replica is up",
+        replicas
+            .iter()
+            .map(|x| {
+                let info = x.info.as_ref().unwrap();
+                let up = info.get("master_link_status").map(|x| x.as_str()) == Some("up");
+                (node_labels(x), if up { 1.0 } else { 0.0 })
+            })
+            .collect(),
+    );
+    family(
+        &mut out,
+        "rckit_node_replication_lag_bytes",
+        "replication offset of the master not yet applied by the replica",
+        replicas
+            .iter()
+            .filter_map(|x| {
+                let master = offsets.get(x.entry.master_id.as_ref()?.as_str())?;
+                let offset = number(x.info.as_ref()?, "slave_repl_offset")?;
+                Some((node_labels(x), (master - offset).max(0.0)))
+            })
+            .collect(),
+    );
+    out
+}
+
+fn family(out: &mut String, name: &str, help: &str, samples: Vec<(String, f64)>) {
+    if samples.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{} {}", name, labels, value);
+    }
+}
+
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<String> = pairs
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
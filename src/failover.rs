@@ -0,0 +1,194 @@
+use cluster::{self, AsResult, Error, Node};
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_best_replica() {
+    let replicas = vec![
+        Some(Offset {
+            link_up: true,
+            offset: 100,
+        }),
+        None,
+        Some(Offset {
+            link_up: true,
+            offset: 120,
+        }),
+        Some(Offset {
+            link_up: false,
+            offset: 200,
+        }),
+    ];
+    assert_eq!(best_replica(&replicas), Some(2));
+    assert_eq!(
+        best_replica(&[
+            None,
+            Some(Offset {
+                link_up: false,
+                offset: 1
+            })
+        ]),
+        Some(1)
+    );
+    assert_eq!(best_replica(&[None, None]), None);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Default,
+    Force,
+    Takeover,
+}
+
+impl Mode {
+    fn arg(self) -> Option<&'static str> {
+        match self {
+            Mode::Default => None,
+            Mode::Force => Some("FORCE"),
+            Mode::Takeover => Some("TAKEOVER"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Offset {
+    link_up: bool,
+    offset: u64,
+}
+
+pub struct Failover {
+    replica: Node,
+    nodes: Vec<Node>,
+}
+
+impl Failover {
+    pub fn new(addr: &str) -> AsResult<Failover> {
+        let mut seed = Node::new(addr.as_bytes())?;
+        seed.connect()?;
+        let nodes = seed.nodes()?;
+        if !seed.is_master() {
+            return Ok(Failover {
+                replica: seed,
+                nodes,
+            });
+        }
+        let replicas: Vec<&Node> = nodes
+            .iter()
+            .filter(|x| x.slaveof.as_ref() == Some(&seed.name))
+            .collect();
+        let offsets: Vec<Option<Offset>> = replicas
+            .iter()
+            .map(|x| match x.redis_info() {
+                Ok(info) => {
+                    let offset = Offset {
+                        link_up: info.get("master_link_status").map(|x| x.as_str()) == Some("up"),
+                        offset: info
+                            .get("slave_repl_offset")
+                            .and_then(|x| x.parse().ok())
+                            .unwrap_or(0),
+                    };
+                    println!(
+                        "replica {} offset {} link {}",
+                        x.addr(),
+                        offset.offset,
+                        if offset.link_up { "up" } else { "down" }
+                    );
+                    Some(offset)
+                }
+                Err(err) => {
+                    eprintln!("skip replica {}: {}", x.addr(), err);
+                    None
+                }
+            })
+            .collect();
+        let replica = best_replica(&offsets)
+            .map(|idx| replicas[idx].clone())
+            .ok_or_else(|| {
+                Error::State(format!("master {} has no reachable replica", seed.addr()))
+            })?;
+        Ok(Failover { replica, nodes })
+    }
+
+    pub fn run(&self, mode: Mode, timeout: Duration) -> AsResult<()> {
+        let master = self
+            .replica
+            .slaveof
+            .clone()
+            .ok_or_else(|| Error::State(format!("{} is not a replica", self.replica.addr())))?;
+        println!(
+            "failover {} {} to replace master {}",
+            self.replica.addr(),
+            self.replica.name,
+            master
+        );
+        let start = Instant::now();
+        self.replica.failover(mode.arg())?;
+        if cluster::is_dry_run() {
+            return Ok(());
+        }
+
+        let mut promoted = false;
+        loop {
+            let views = self.views();
+            if !promoted && views.get(&self.replica.name) == Some(&true) {
+                promoted = true;
+                println!(
+                    "{} promoted after {:.2}s",
+                    self.replica.addr(),
+                    start.elapsed().as_secs_f64()
+                );
+            }
+            if promoted && views.values().all(|x| *x) {
+                println!(
+                    "all {} reachable nodes see {} as master after {:.2}s",
+                    views.len(),
+                    self.replica.addr(),
+                    start.elapsed().as_secs_f64()
+                );
+                return Ok(());
+            }
+            if start.elapsed() > timeout {
+                let lagging: Vec<String> = self
+                    .nodes
+                    .iter()
+                    .filter(|x| views.get(&x.name) == Some(&false))
+                    .map(|x| x.addr())
+                    .collect();
+                return Err(Error::State(format!(
+                    "failover of {} not visible on {} after {}s",
+                    self.replica.addr(),
+                    lagging.join(","),
+                    timeout.as_secs()
+                )));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    // node id -> whether that node sees the replica as master, unreachable nodes are left out
+    fn views(&self) -> HashMap<String, bool> {
+        let mut views = HashMap::new();
+        for node in &self.nodes {
+            if node.address().host.is_empty() {
+                continue;
+            }
+            if let Ok(nodes) = node.nodes() {
+                let seen = nodes
+                    .iter()
+                    .any(|x| x.name == self.replica.name && x.is_master());
+                views.insert(node.name.clone(), seen);
+            }
+        }
+        views
+    }
+}
+
+fn best_replica(offsets: &[Option<Offset>]) -> Option<usize> {
+    offsets
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, x)| x.map(|x| (idx, x)))
+        .max_by_key(|(_, x)| (x.link_up, x.offset))
+        .map(|(idx, _)| idx)
+}
@@ -0,0 +1,325 @@
+use addr::Addr;
+use cluster::{AsResult, Error, Node};
+use report::{self, Format};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+use util;
+
+#[test]
+fn test_parse_monitor() {
+    assert_eq!(
+        parse_monitor(r#"1339518083.107412 [0 127.0.0.1:60866] "set" "user:1" "bar""#),
+        Some(b"user:1".to_vec())
+    );
+    assert_eq!(
+        parse_monitor(r#"1339518099.363765 [0 lua] "GET" "a \"b\"\\c\x01""#),
+        Some(b"a \"b\"\\c\x01".to_vec())
+    );
+    assert_eq!(
+        parse_monitor(r#"1339518083.107412 [0 127.0.0.1:60866] "ping""#),
+        None
+    );
+    assert_eq!(
+        parse_monitor(r#"1339518083.107412 [0 127.0.0.1:60866] "cluster" "nodes""#),
+        None
+    );
+    assert_eq!(parse_monitor("OK"), None);
+}
+
+#[test]
+fn test_top() {
+    let mut counts = HashMap::new();
+    counts.insert(b"a".to_vec(), 3);
+    counts.insert(b"b".to_vec(), 9);
+    counts.insert(b"c".to_vec(), 5);
+    let keys: Vec<(Vec<u8>, u64)> = top(counts.into_iter(), 2);
+    assert_eq!(keys, vec![(b"b".to_vec(), 9), (b"c".to_vec(), 5)]);
+}
+
+// commands whose first argument is not a key
+const KEYLESS: &[&str] = &[
+    "acl",
+    "auth",
+    "bgrewriteaof",
+    "bgsave",
+    "client",
+    "cluster",
+    "command",
+    "config",
+    "dbsize",
+    "debug",
+    "discard",
+    "echo",
+    "eval",
+    "evalsha",
+    "exec",
+    "fcall",
+    "flushall",
+    "flushdb",
+    "function",
+    "hello",
+    "info",
+    "keys",
+    "lastsave",
+    "latency",
+    "memory",
+    "migrate",
+    "module",
+    "monitor",
+    "multi",
+    "object",
+    "ping",
+    "psync",
+    "publish",
+    "quit",
+    "randomkey",
+    "readonly",
+    "readwrite",
+    "replconf",
+    "role",
+    "save",
+    "scan",
+    "script",
+    "select",
+    "slowlog",
+    "ssubscribe",
+    "subscribe",
+    "sync",
+    "time",
+    "unwatch",
+    "wait",
+    "xread",
+    "xreadgroup",
+];
+
+#[derive(Debug)]
+pub enum Mode {
+    Lfu,
+    Monitor(Duration),
+}
+
+#[derive(Debug, Serialize)]
+pub struct HotKey {
+    pub key: String,
+    // the LFU counter, or the number of commands seen while monitoring
+    pub hits: u64,
+    pub slot: usize,
+    pub node: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HotKeys {
+    pub mode: String,
+    pub keys: Vec<HotKey>,
+}
+
+pub struct Scan {
+    masters: Vec<Addr>,
+    top: usize,
+    count: usize,
+}
+
+impl Scan {
+    pub fn new(addr: &str, top: usize, count: usize) -> AsResult<Scan> {
+        let seed = Node::new(addr.as_bytes())?;
+        let mut masters: Vec<Addr> = seed
+            .nodes()?
+            .iter()
+            .filter(|x| x.is_master() && !x.slots().is_empty())
+            .map(|x| x.address())
+            .collect();
+        if masters.is_empty() {
+            return Err(Error::State("no master serves any slot".to_string()));
+        }
+        masters.sort_by_key(|x| x.to_string());
+        Ok(Scan {
+            masters,
+            top,
+            count,
+        })
+    }
+
+    // the masters whose maxmemory-policy keeps no LFU counter, with their policy
+    pub fn without_lfu(&self) -> AsResult<Vec<(String, String)>> {
+        let mut result = Vec::new();
+        for addr in &self.masters {
+            let node = Node::from_addr(addr.clone());
+            let policy = node.config_get("maxmemory-policy")?;
+            if !policy.contains("lfu") {
+                result.push((node.addr(), policy));
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn run(&self, mode: &Mode) -> AsResult<HotKeys> {
+        let (top_n, count) = (self.top, self.count);
+        let handles: Vec<_> = self
+            .masters
+            .iter()
+            .map(|addr| {
+                let addr = addr.clone();
+                let monitor = match mode {
+                    Mode::Lfu => None,
+                    Mode::Monitor(duration) => Some(*duration),
+                };
+                thread::spawn(move || {
+                    let node = Node::from_addr(addr);
+                    let keys = match monitor {
+                        None => scan_freqs(&node, top_n, count)?,
+                        Some(duration) => sample_monitor(&node, top_n, duration)?,
+                    };
+                    Ok((node.addr(), keys))
+                })
+            })
+            .collect();
+        let mut keys = Vec::new();
+        for handle in handles {
+            let result: AsResult<_> = handle.join().expect("hotkeys worker panicked");
+            let (node, node_keys) = result?;
+            keys.extend(node_keys.into_iter().map(|(key, hits)| HotKey {
+                slot: util::key_slot(&key),
+                key: String::from_utf8_lossy(&key).into_owned(),
+                hits,
+                node: node.clone(),
+            }));
+        }
+        keys.sort_by(|a, b| b.hits.cmp(&a.hits).then_with(|| a.key.cmp(&b.key)));
+        keys.truncate(top_n);
+        Ok(HotKeys {
+            mode: match mode {
+                Mode::Lfu => "lfu",
+                Mode::Monitor(_) => "monitor",
+            }
+            .to_string(),
+            keys,
+        })
+    }
+}
+
+impl HotKeys {
+    pub fn write(&self, output: &str, format: &Format) -> io::Result<()> {
+        util::write_output(output, |out| self.write_to(out, format))
+    }
+
+    fn write_to<W: Write>(&self, out: &mut W, format: &Format) -> io::Result<()> {
+        match format {
+            Format::Csv => {
+                writeln!(out, "key,hits,slot,node")?;
+                for key in &self.keys {
+                    writeln!(
+                        out,
+                        "{},{},{},{}",
+                        report::csv_escape(&key.key),
+                        key.hits,
+                        key.slot,
+                        key.node
+                    )?;
+                }
+            }
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn scan_freqs(node: &Node, top_n: usize, count: usize) -> AsResult<Vec<(Vec<u8>, u64)>> {
+    let mut hottest = Vec::new();
+    let mut scanned = 0;
+    let mut cursor = 0;
+    loop {
+        let (next, keys) = node.scan(cursor, count)?;
+        if !keys.is_empty() {
+            let freqs = node.object_freqs(&keys)?;
+            scanned += keys.len();
+            // keys expired or deleted since SCAN have no counter
+            let batch = keys
+                .into_iter()
+                .zip(freqs)
+                .filter_map(|(key, freq)| freq.map(|x| (key, x)));
+            hottest = top(hottest.into_iter().chain(batch), top_n);
+        }
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    eprintln!("scanned {} keys on {}", scanned, node.addr());
+    Ok(hottest)
+}
+
+fn sample_monitor(node: &Node, top_n: usize, duration: Duration) -> AsResult<Vec<(Vec<u8>, u64)>> {
+    let mut counts: HashMap<Vec<u8>, u64> = HashMap::new();
+    let mut commands = 0;
+    node.monitor(duration, |line| {
+        if let Some(key) = parse_monitor(line) {
+            commands += 1;
+            *counts.entry(key).or_insert(0) += 1;
+        }
+    })?;
+    eprintln!(
+        "sampled {} commands on {} keys of {} in {}s",
+        commands,
+        counts.len(),
+        node.addr(),
+        duration.as_secs()
+    );
+    Ok(top(counts.into_iter(), top_n))
+}
+
+fn top<I: Iterator<Item = (Vec<u8>, u64)>>(keys: I, n: usize) -> Vec<(Vec<u8>, u64)> {
+    let mut keys: Vec<(Vec<u8>, u64)> = keys.collect();
+    keys.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    keys.truncate(n);
+    keys
+}
+
+// the first key of a MONITOR line like `1339518083.107412 [0 127.0.0.1:60866] "get" "foo"`
+fn parse_monitor(line: &str) -> Option<Vec<u8>> {
+    let (_, args) = line.split_once("] ")?;
+    let mut args = quoted(args.as_bytes()).into_iter();
+    let command = String::from_utf8(args.next()?).ok()?.to_lowercase();
+    if KEYLESS.contains(&command.as_str()) {
+        return None;
+    }
+    args.next()
+}
+
+// splits the arguments quoted by sdscatrepr, undoing its escapes
+fn quoted(input: &[u8]) -> Vec<Vec<u8>> {
+    let mut args = Vec::new();
+    let mut iter = input.iter().cloned();
+    while let Some(byte) = iter.next() {
+        if byte != b'"' {
+            continue;
+        }
+        let mut arg = Vec::new();
+        while let Some(byte) = iter.next() {
+            match byte {
+                b'"' => break,
+                b'\\' => match iter.next() {
+                    Some(b'n') => arg.push(b'\n'),
+                    Some(b'r') => arg.push(b'\r'),
+                    Some(b't') => arg.push(b'\t'),
+                    Some(b'a') => arg.push(7),
+                    Some(b'b') => arg.push(8),
+                    Some(b'x') => {
+                        let hex: Vec<u8> = iter.by_ref().take(2).collect();
+                        let hex = String::from_utf8_lossy(&hex).into_owned();
+                        arg.push(u8::from_str_radix(&hex, 16).unwrap_or(b'?'));
+                    }
+                    Some(other) => arg.push(other),
+                    None => break,
+                },
+                _ => arg.push(byte),
+            }
+        }
+        args.push(arg);
+    }
+    args
+}
@@ -0,0 +1,425 @@
+use addr::Addr;
+use cluster::{self, AsResult, Error, Node};
+use parser::{Flag, NodeEntry};
+use schedule::{self, Move};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(test)]
+fn record(slot: usize, src: &str, dst: &str) -> Record {
+    Record {
+        slot,
+        src: src.to_string(),
+        src_addr: String::new(),
+        dst: dst.to_string(),
+        dst_addr: String::new(),
+    }
+}
+
+#[test]
+fn test_parse_journal() {
+    let header = Header {
+        version: VERSION,
+        created: 0,
+        moves: vec![record(1, "aaaa", "bbbb"), record(2, "aaaa", "bbbb")],
+    };
+    let content = format!(
+        "{}\n{}\n{{\"slot\":2,\"st",
+        serde_json::to_string(&header).unwrap(),
+        serde_json::to_string(&Progress {
+            slot: 1,
+            state: "done".to_string()
+        })
+        .unwrap()
+    );
+    let moves = parse(&content).unwrap();
+    assert_eq!(moves.len(), 2);
+    assert_eq!(
+        moves.iter().map(|x| (x.0.slot, x.1)).collect::<Vec<_>>(),
+        vec![(1, true), (2, false)]
+    );
+
+    // a later run of the same process appends its own plan, progress lines follow their plan
+    let header = Header {
+        version: VERSION,
+        created: 0,
+        moves: vec![record(1, "bbbb", "cccc")],
+    };
+    let appended = format!(
+        "{}\n{}\n",
+        content.rsplit_once('\n').unwrap().0,
+        serde_json::to_string(&header).unwrap()
+    );
+    let moves = parse(&appended).unwrap();
+    assert_eq!(
+        moves.iter().map(|x| (x.0.slot, x.1)).collect::<Vec<_>>(),
+        vec![(1, true), (2, false), (1, false)]
+    );
+
+    assert!(parse("").is_err());
+    assert!(parse("{\"version\":9,\"created\":0,\"moves\":[]}").is_err());
+    let broken = format!("{}\nnot json\n{{\"slot\":1,\"state\":\"done\"}}", content);
+    assert!(parse(&broken).is_err());
+}
+
+#[test]
+fn test_create_existing() {
+    let path = ::std::env::temp_dir().join(format!("rckit-test-{}.journal", ::std::process::id()));
+    let path = path.to_str().unwrap();
+    fs::write(path, "keep").unwrap();
+    assert!(Journal::create(path, &[]).is_err());
+    assert_eq!(fs::read_to_string(path).unwrap(), "keep");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_step() {
+    let entries = ::parser::parse_nodes(
+        "\
+aaaa 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-99 [1->-bbbb]
+bbbb 127.0.0.1:7001@17001 master - 0 0 2 connected 100-199 [1-<-aaaa] [100-<-aaaa]
+cccc 127.0.0.1:7002@17002 master - 0 0 3 connected 200-299
+",
+    )
+    .unwrap();
+    let live = Live::new(&entries);
+    assert_eq!(live.step(&record(1, "aaaa", "bbbb")), Step::Migrate);
+    assert_eq!(live.step(&record(2, "aaaa", "bbbb")), Step::Migrate);
+    assert_eq!(
+        live.step(&record(100, "aaaa", "bbbb")),
+        Step::Finish {
+            src_open: false,
+            dst_open: true
+        }
+    );
+    assert_eq!(
+        live.step(&record(101, "aaaa", "bbbb")),
+        Step::Finish {
+            src_open: false,
+            dst_open: false
+        }
+    );
+    assert_eq!(
+        live.step(&record(200, "aaaa", "bbbb")),
+        Step::Abandon {
+            src_open: false,
+            dst_open: false
+        }
+    );
+    assert_eq!(
+        live.step(&record(1, "aaaa", "dddd")),
+        Step::Abandon {
+            src_open: true,
+            dst_open: false
+        }
+    );
+}
+
+pub const VERSION: u32 = 1;
+
+// None once journaling is turned off, unset means a fresh file per run
+static PATH: OnceLock<String> = OnceLock::new();
+// whether this process already created the journal, later runs append to it
+static CREATED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_path(path: &str) {
+    let _ = PATH.set(path.to_string());
+}
+
+// the journal of the slot moves about to run, None unless --journal is given
+pub fn start(moves: &[Move]) -> AsResult<Option<Journal>> {
+    let path = match PATH.get() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    if CREATED.load(Ordering::SeqCst) {
+        let mut journal = Journal::open(path)?;
+        journal.plan(moves)?;
+        return Ok(Some(journal));
+    }
+    let journal = Journal::create(path, moves)?;
+    CREATED.store(true, Ordering::SeqCst);
+    Ok(Some(journal))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    created: u64,
+    moves: Vec<Record>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Record {
+    slot: usize,
+    src: String,
+    src_addr: String,
+    dst: String,
+    dst_addr: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Progress {
+    slot: usize,
+    state: String,
+}
+
+// every run writes its plan as a line, then one line is appended per finished slot
+pub struct Journal {
+    path: String,
+    file: File,
+}
+
+impl Journal {
+    pub fn create(path: &str, moves: &[Move]) -> AsResult<Journal> {
+        // a journal left by an earlier run may still be needed to resume it
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::AlreadyExists => Error::State(format!(
+                    "journal {} already exists, resume it or choose another --journal",
+                    path
+                )),
+                _ => err.into(),
+            })?;
+        let mut journal = Journal {
+            path: path.to_string(),
+            file,
+        };
+        journal.plan(moves)?;
+        Ok(journal)
+    }
+
+    fn plan(&mut self, moves: &[Move]) -> AsResult<()> {
+        let header = Header {
+            version: VERSION,
+            created: now(),
+            moves: moves
+                .iter()
+                .map(|x| Record {
+                    slot: x.slot,
+                    src: x.src.name.clone(),
+                    src_addr: x.src.addr.to_string(),
+                    dst: x.dst.name.clone(),
+                    dst_addr: x.dst.addr.to_string(),
+                })
+                .collect(),
+        };
+        self.append(&serde_json::to_string(&header).map_err(io::Error::other)?)?;
+        println!("journal {} slot moves to {}", moves.len(), self.path);
+        Ok(())
+    }
+
+    fn open(path: &str) -> AsResult<Journal> {
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Journal {
+            path: path.to_string(),
+            file,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn done(&mut self, slot: usize) -> io::Result<()> {
+        let progress = Progress {
+            slot,
+            state: "done".to_string(),
+        };
+        self.append(&serde_json::to_string(&progress).map_err(io::Error::other)?)
+    }
+
+    fn append(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.file, "{}", line)?;
+        self.file.sync_data()
+    }
+}
+
+// the planned moves of every run and whether they are done, ignoring a last line cut by a crash
+fn parse(content: &str) -> Result<Vec<(Record, bool)>, String> {
+    let mut lines = content.lines().filter(|x| !x.trim().is_empty()).peekable();
+    let mut moves: Vec<(Record, bool)> = Vec::new();
+    // the moves of the last plan, progress lines only refer to them
+    let mut run = 0;
+    let mut first = true;
+    while let Some(line) = lines.next() {
+        if let Ok(progress) = serde_json::from_str::<Progress>(line) {
+            if first {
+                return Err("bad journal header: no plan before progress".to_string());
+            }
+            if progress.state == "done" {
+                if let Some(x) = moves[run..].iter_mut().find(|x| x.0.slot == progress.slot) {
+                    x.1 = true;
+                }
+            }
+            continue;
+        }
+        let header: Header = match serde_json::from_str(line) {
+            Ok(header) => header,
+            Err(_) if !first && lines.peek().is_none() => break,
+            Err(err) if first => return Err(format!("bad journal header: {}", err)),
+            Err(err) => return Err(format!("bad journal line {:?}: {}", line, err)),
+        };
+        if header.version != VERSION {
+            return Err(format!(
+                "unsupported journal version {}, expect {}",
+                header.version, VERSION
+            ));
+        }
+        first = false;
+        run = moves.len();
+        moves.extend(header.moves.into_iter().map(|x| (x, false)));
+    }
+    if first {
+        return Err("empty journal".to_string());
+    }
+    Ok(moves)
+}
+
+#[derive(Debug, PartialEq)]
+enum Step {
+    // the source still owns the slot, migrating it again picks up where it stopped
+    Migrate,
+    // the destination owns the slot, only the open states are left to close
+    Finish { src_open: bool, dst_open: bool },
+    // the slot moved elsewhere or a node is gone, the open states are cleared
+    Abandon { src_open: bool, dst_open: bool },
+}
+
+struct Live<'a> {
+    nodes: HashMap<&'a str, &'a NodeEntry>,
+    owners: HashMap<usize, &'a str>,
+}
+
+impl<'a> Live<'a> {
+    fn new(entries: &'a [NodeEntry]) -> Live<'a> {
+        let mut owners = HashMap::new();
+        for entry in entries.iter().filter(|x| x.has(&Flag::Master)) {
+            for slot in entry.slot_list() {
+                owners.insert(slot, entry.id.as_str());
+            }
+        }
+        Live {
+            nodes: entries.iter().map(|x| (x.id.as_str(), x)).collect(),
+            owners,
+        }
+    }
+
+    fn step(&self, record: &Record) -> Step {
+        let src = self.nodes.get(record.src.as_str());
+        let dst = self.nodes.get(record.dst.as_str());
+        let src_open = src.is_some_and(|x| x.migrating.contains_key(&record.slot));
+        let dst_open = dst.is_some_and(|x| x.importing.contains_key(&record.slot));
+        let owner = self.owners.get(&record.slot).cloned();
+        match (src, dst) {
+            (Some(_), Some(_)) if owner == Some(record.src.as_str()) => Step::Migrate,
+            (_, Some(_)) if owner == Some(record.dst.as_str()) => {
+                Step::Finish { src_open, dst_open }
+            }
+            _ => Step::Abandon { src_open, dst_open },
+        }
+    }
+
+    fn node(&self, id: &str, addr: &str) -> AsResult<Node> {
+        let addr = match self.nodes.get(id) {
+            Some(entry) => entry.addr.clone(),
+            None => Addr::parse(addr)?,
+        };
+        let mut node = Node::from_addr(addr);
+        node.name = id.to_string();
+        Ok(node)
+    }
+}
+
+pub fn resume(path: &str) -> AsResult<()> {
+    let content = fs::read_to_string(path)?;
+    let moves = parse(&content).map_err(Error::State)?;
+    let records: Vec<Record> = moves.iter().map(|x| x.0.clone()).collect();
+    let pending: Vec<&Record> = moves.iter().filter(|x| !x.1).map(|x| &x.0).collect();
+    println!(
+        "{} of {} slot moves in {} are done",
+        records.len() - pending.len(),
+        records.len(),
+        path
+    );
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let mut entries = live_entries(&records)?;
+    cluster::fill_open_slots(&mut entries);
+    let live = Live::new(&entries);
+    let mut journal = if cluster::is_dry_run() {
+        None
+    } else {
+        Some(Journal::open(path)?)
+    };
+    let mut moves = Vec::new();
+    for record in pending {
+        let step = live.step(record);
+        let src = live.node(&record.src, &record.src_addr)?;
+        let dst = live.node(&record.dst, &record.dst_addr)?;
+        match step {
+            Step::Migrate => moves.push(Move::new(&src, &dst, record.slot)),
+            Step::Finish { src_open, dst_open } => {
+                println!("slot {} already on {}, close it", record.slot, dst.addr());
+                if dst_open {
+                    dst.setslot("NODE", dst.name.clone(), record.slot)?;
+                }
+                if src_open {
+                    src.setslot("NODE", dst.name.clone(), record.slot)?;
+                }
+                if let Some(ref mut journal) = journal {
+                    journal.done(record.slot)?;
+                }
+            }
+            Step::Abandon { src_open, dst_open } => {
+                eprintln!(
+                    "warning: slot {} is no longer owned by {} or a node is gone, skip it; \
+                     run fix if keys of the slot were left behind",
+                    record.slot,
+                    src.addr()
+                );
+                if src_open {
+                    src.setslot_stable(record.slot)?;
+                }
+                if dst_open {
+                    dst.setslot_stable(record.slot)?;
+                }
+            }
+        }
+    }
+    if moves.is_empty() {
+        return Ok(());
+    }
+    println!("resume {} slot moves", moves.len());
+    schedule::run_with(moves, journal)
+}
+
+fn live_entries(records: &[Record]) -> AsResult<Vec<NodeEntry>> {
+    let mut last = Error::State("journal has no move".to_string());
+    let mut seen = HashSet::new();
+    for addr in records.iter().flat_map(|x| vec![&x.src_addr, &x.dst_addr]) {
+        if !seen.insert(addr) {
+            continue;
+        }
+        match Node::new(addr.as_bytes()).and_then(|node| node.entries()) {
+            Ok(entries) => return Ok(entries),
+            Err(err) => last = err,
+        }
+    }
+    Err(last)
+}
@@ -3,175 +3,518 @@
 #[macro_use]
 extern crate clap;
 extern crate redis;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate serde_yaml;
 
 mod add;
+mod addr;
+mod apply;
+mod backup;
+mod bigkeys;
+mod check;
 mod cluster;
 mod create;
+mod exporter;
+mod failover;
+mod hotkeys;
+mod journal;
+mod parser;
+mod rdb;
+mod rebalance;
+mod report;
+mod schedule;
+mod slots;
+mod top;
+mod topology;
 mod util;
+mod zone;
 
 use add::Add;
-use clap::App;
-use cluster::{Cluster, Node};
+use backup::Backup;
+use check::Health;
+use clap::{App, ArgMatches};
+use cluster::{AsResult, Cluster, Error, Node};
 use create::Create;
+use failover::Failover;
+use rebalance::Rebalance;
+use report::Report;
+use schedule::Move;
+use std::env;
+use std::fs;
+use std::process;
 use std::{thread, time};
+use topology::Topology;
 
 pub fn run() {
-
     let yaml = load_yaml!("cli.yml");
     let matches = App::from_yaml(yaml).get_matches();
+    let user = matches
+        .value_of("user")
+        .map(|x| x.to_string())
+        .or_else(|| env::var("RCKIT_USER").ok());
+    let password = matches
+        .value_of("password")
+        .map(|x| x.to_string())
+        .or_else(|| env::var("RCKIT_PASSWORD").ok());
+    match (user, password) {
+        (user, Some(password)) => cluster::set_auth(cluster::Auth { user, password }),
+        (Some(_), None) => {
+            eprintln!("rckit: --user requires --password or RCKIT_PASSWORD");
+            process::exit(1);
+        }
+        (None, None) => {}
+    }
+    if matches.is_present("tls") {
+        let read = |name: &str| -> Option<Vec<u8>> {
+            matches.value_of(name).map(|path| {
+                fs::read(path).unwrap_or_else(|err| {
+                    eprintln!("rckit: read {} fail: {}", path, err);
+                    process::exit(1);
+                })
+            })
+        };
+        cluster::set_tls(cluster::Tls {
+            ca: read("tls-ca"),
+            cert: read("tls-cert"),
+            key: read("tls-key"),
+            insecure: matches.is_present("tls-insecure"),
+        });
+    }
+    let number = |name: &str, default: usize| -> usize {
+        if !matches.is_present(name) {
+            return default;
+        }
+        clap::value_t!(matches.value_of(name), usize).unwrap_or_else(|e| e.exit())
+    };
+    let default = cluster::Migration::default();
+    let migration = cluster::Migration {
+        batch: number("migrate-batch", default.batch),
+        timeout: number("migrate-timeout", default.timeout),
+        replace: matches.is_present("migrate-replace"),
+        retries: number("migrate-retries", default.retries),
+        parallel: number("migrate-parallel", default.parallel),
+        per_node: number("migrate-per-node", default.per_node),
+    };
+    if migration.batch == 0 || migration.parallel == 0 || migration.per_node == 0 {
+        eprintln!("rckit: --migrate-batch, --migrate-parallel and --migrate-per-node must be greater than 0");
+        process::exit(1);
+    }
+    cluster::set_migration(migration);
+    if let Some(path) = matches.value_of("journal") {
+        journal::set_path(path);
+    }
+    if let Some(path) = matches.value_of("zones") {
+        zone::load(path).unwrap_or_else(|err| {
+            eprintln!("rckit: load zones from {} fail: {}", path, err);
+            process::exit(1);
+        });
+    }
+    if matches.is_present("dry-run") {
+        cluster::set_dry_run(true);
+        println!("dry run, no mutating command will be sent");
+    }
 
-    if let Some(sub_m) = matches.subcommand_matches("create") {
-        let slave_count = clap::value_t!(sub_m.value_of("replicate"), usize).unwrap();
-        let mut master_count = clap::value_t!(sub_m.value_of("master"), usize).unwrap();
-        let node: Vec<&str> = sub_m.values_of("node").unwrap().collect();
-        let mut create = Create::new(node, master_count, slave_count).unwrap();
-        create.cluster.check().expect("check node err");
-        create.init_slots();
-        create.add_slots();
-        create.set_config_epoch();
-        create.join_cluster();
+    let result = match matches.subcommand() {
+        ("create", Some(sub_m)) => create(sub_m),
+        ("add", Some(sub_m)) => add(sub_m),
+        ("delete", Some(sub_m)) => delete(sub_m),
+        ("migrate", Some(sub_m)) => migrate(sub_m),
+        ("fix", Some(sub_m)) => fix(sub_m),
+        ("reshard", Some(sub_m)) => reshard(sub_m),
+        ("report", Some(sub_m)) => report(sub_m),
+        ("check", Some(sub_m)) => check(sub_m),
+        ("failover", Some(sub_m)) => failover(sub_m),
+        ("topology", Some(sub_m)) => topology(sub_m),
+        ("apply", Some(sub_m)) => apply(sub_m),
+        ("backup", Some(sub_m)) => backup(sub_m),
+        ("rdb", Some(sub_m)) => rdb(sub_m),
+        ("bigkeys", Some(sub_m)) => bigkeys(sub_m),
+        ("hotkeys", Some(sub_m)) => hotkeys(sub_m),
+        ("slots", Some(sub_m)) => slots(sub_m),
+        ("exporter", Some(sub_m)) => exporter(sub_m),
+        ("top", Some(sub_m)) => top(sub_m),
+        ("resume", Some(sub_m)) => resume(sub_m),
+        _ => {
+            println!("{}", matches.usage());
+            Ok(())
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("rckit: {}", err);
+        process::exit(1);
+    }
+}
+
+fn create(sub_m: &ArgMatches) -> AsResult<()> {
+    let slave_count =
+        clap::value_t!(sub_m.value_of("replicate"), usize).unwrap_or_else(|e| e.exit());
+    let master_count = clap::value_t!(sub_m.value_of("master"), usize).unwrap_or_else(|e| e.exit());
+    let node: Vec<&str> = sub_m.values_of("node").unwrap().collect();
+    let mut create = Create::new(node, master_count, slave_count)?;
+    create.cluster.check()?;
+    create.init_slots(sub_m.is_present("allow-same-host"))?;
+    create.add_slots()?;
+    create.set_config_epoch()?;
+    create.join_cluster()?;
+    if !cluster::is_dry_run() {
         println!("wait consistent...");
         while !create.consistent() {
             thread::sleep(time::Duration::from_secs(1));
         }
-        create.set_slave().expect("set slave err");
-        return;
-    }
-
-    if let Some(sub_m) = matches.subcommand_matches("add") {
-        let cluster = sub_m
-            .value_of("cluster")
-            .expect("must spec existing cluster node");
-        let nodes: Vec<&str> = sub_m
-            .values_of("node")
-            .expect("must spec at least one node be add to cluster")
-            .collect();
-        println!("add node {:?} to cluster {}", nodes, cluster);
-        let mut add = Add::new(
-            cluster.to_string(),
-            nodes.iter().map(|x| x.to_string()).collect(),
-        )
-        .unwrap();
-        add.cluster.check().expect("check cluste nodes fail");
-        let _: () = add.add_node().expect("add node fail");
-        while !add.cluster.consistency() {
-            eprintln!("wait consistent fail");
-            thread::sleep(time::Duration::from_secs(1));
-        }
-        add.set_slave();
-        return;
-    }
-
-    if let Some(sub_m) = matches.subcommand_matches("delete") {
-        let newnodes: Vec<&str> = sub_m
-            .values_of("node")
-            .expect("get node to deleted fail")
-            .collect();
-        let new_node = Node::new(newnodes[0].as_bytes()).expect("new node fail");
-        let nodes = new_node.nodes();
-        for n in &nodes {
-            println!("nodes {:?} ", n);
-        }
-        let cluster = Cluster::new(nodes);
+    }
+    create.set_slave()
+}
 
-        for node in newnodes {
-            let del_node = cluster.node(node).expect("get node from cluster fail");
+fn add(sub_m: &ArgMatches) -> AsResult<()> {
+    let cluster = sub_m
+        .value_of("cluster")
+        .expect("must spec existing cluster node");
+    let nodes: Vec<&str> = sub_m
+        .values_of("node")
+        .expect("must spec at least one node be add to cluster")
+        .collect();
+    println!("add node {:?} to cluster {}", nodes, cluster);
+    let mut add = Add::new(
+        cluster.to_string(),
+        nodes.iter().map(|x| x.to_string()).collect(),
+        sub_m.is_present("allow-same-zone"),
+    )?;
+    add.cluster.check()?;
+    add.add_node()?;
+    while !cluster::is_dry_run() && !add.cluster.consistency() {
+        eprintln!("wait consistent fail");
+        thread::sleep(time::Duration::from_secs(1));
+    }
+    add.set_slave()
+}
 
-            println!("delete node {:?}", del_node);
-            cluster.delete_node(del_node);
-        }
-        return;
+fn delete(sub_m: &ArgMatches) -> AsResult<()> {
+    let newnodes: Vec<&str> = sub_m
+        .values_of("node")
+        .expect("get node to deleted fail")
+        .collect();
+    let new_node = Node::new(newnodes[0].as_bytes())?;
+    let nodes = new_node.nodes()?;
+    for n in &nodes {
+        println!("nodes {:?} ", n);
     }
+    let cluster = Cluster::new(nodes);
 
-    if let Some(sub_m) = matches.subcommand_matches("migrate") {
-        let arg = (
-            sub_m.value_of("src"),
-            sub_m.value_of("dst"),
-            clap::value_t!(sub_m.value_of("count"), usize),
-        );
-        let migrate = |src: &Node, dst: &Node, count: &[usize]| {
-            for slot in count {
-                cluster::migrate_slot(&src, dst, *slot)
+    for node in newnodes {
+        let del_node = cluster
+            .node(node)
+            .ok_or_else(|| Error::State(format!("node {} is not in cluster", node)))?;
+
+        println!("delete node {:?}", del_node);
+        cluster.delete_node(del_node)?;
+    }
+    Ok(())
+}
+
+fn migrate(sub_m: &ArgMatches) -> AsResult<()> {
+    if sub_m.value_of("src").is_none() && sub_m.value_of("dst").is_none() {
+        return Err(Error::State("must spec src or dst node".to_string()));
+    }
+    let arg = (
+        sub_m.value_of("src"),
+        sub_m.value_of("dst"),
+        clap::value_t!(sub_m.value_of("count"), usize),
+    );
+    let plan = |src: &Node, dst: &Node, slots: &[usize]| -> Vec<Move> {
+        slots
+            .iter()
+            .map(|slot| Move::new(src, dst, *slot))
+            .collect()
+    };
+    let connect = |addr: &str| -> AsResult<Node> {
+        let mut node = Node::new(addr.as_bytes())?;
+        node.connect()?;
+        Ok(node)
+    };
+    let moves = match arg {
+        (Some(src), Some(dst), Ok(count)) => {
+            let src_node = connect(src)?;
+            let dst_node = connect(dst)?;
+            let slots = src_node.slots();
+            plan(&src_node, &dst_node, &slots[..count.min(slots.len())])
+        }
+        (Some(src), _, Ok(count)) => {
+            let src_node = connect(src)?;
+            let src_name = src_node.name.clone();
+            let masters: Vec<Node> = src_node
+                .nodes()?
+                .into_iter()
+                .filter(|x| x.is_master() && x.name != src_name)
+                .collect();
+            if masters.is_empty() {
+                return Err(Error::State("no other master to migrate to".to_string()));
             }
-        };
-        match arg {
-            (Some(src), Some(dst), Ok(count)) => {
-                let mut src_node = Node::new(src.as_bytes()).unwrap();
-                let mut dst_node = Node::new(dst.as_bytes()).unwrap();
-                src_node.connect();
-                dst_node.connect();
-                let slots = src_node.slots();
-                migrate(&mut src_node, &dst_node, &slots[..count])
+            let slots = src_node.slots();
+            let mut dist = util::divide(count.min(slots.len()), masters.len());
+            let mut idx = 0;
+            let mut moves = Vec::new();
+            for master in masters.into_iter() {
+                let num = dist.pop().unwrap();
+                moves.extend(plan(&src_node, &master, &slots[idx..idx + num]));
+                idx += num;
             }
-            (Some(src), _, Ok(count)) => {
-                let mut src_node = Node::new(src.as_bytes()).unwrap();
-                src_node.connect();
-                let src_name = src_node.name.clone();
-                let masters: Vec<Node> = src_node
-                    .nodes()
-                    .into_iter()
-                    .filter(|x| x.is_master() && x.name != src_name)
-                    .collect();
-                let mut dist = util::divide(count, masters.len());
-                let mut idx = 0;
-                let mut slots = src_node.slots();
-
-                for master in masters.into_iter() {
-                    let num = dist.pop().unwrap();
-                    let migra = &slots[idx..idx + num];
-                    migrate(&mut src_node, &master, migra);
-                    idx += num;
-                }
+            moves
+        }
+        (Some(src), Some(dst), Err(_)) => {
+            let src_node = connect(src)?;
+            let dst_node = connect(dst)?;
+            let slots = src_node.slots();
+            plan(&src_node, &dst_node, &slots[..])
+        }
+        (None, Some(dst), Ok(count)) => {
+            let dst_node = connect(dst)?;
+            let dst_name = dst_node.name.clone();
+            let masters: Vec<Node> = dst_node
+                .nodes()?
+                .into_iter()
+                .filter(|x| x.is_master() && x.name != dst_name)
+                .collect();
+            if masters.is_empty() {
+                return Err(Error::State("no other master to migrate from".to_string()));
             }
-            (Some(src), Some(dst), Err(_)) => {
-                let mut src_node = Node::new(src.as_bytes()).unwrap();
-                let mut dst_node = Node::new(dst.as_bytes()).unwrap();
-                src_node.connect();
-                dst_node.connect();
-                let slots = src_node.slots();
-                migrate(&mut src_node, &dst_node, &slots[..])
+            let mut slots = util::divide(count, masters.len());
+            let mut moves = Vec::new();
+            for master in masters {
+                let num = slots.pop().unwrap();
+                let slot = master.slots();
+                moves.extend(plan(&master, &dst_node, &slot[..num.min(slot.len())]));
             }
-            (None, Some(dst), Ok(count)) => {
-                let mut dst_node = Node::new(dst.as_bytes()).unwrap();
-                let dst_name = dst_node.name.clone();
-                let mut masters: Vec<Node> = dst_node
-                    .nodes()
-                    .clone()
-                    .into_iter()
-                    .filter(|x| x.is_master() && x.name != dst_name)
-                    .collect();
-                let mut slots = util::divide(count, masters.len());
-                for master in masters {
-                    let num = slots.pop().unwrap();
-                    let slot = master.slots();
-                    migrate(&master, &dst_node, &slot[..num])
-                }
+            moves
+        }
+        _ => return Err(Error::State("must spec src or dst node".to_string())),
+    };
+    schedule::run(moves)
+}
+
+// the --format of a subcommand, exits naming the accepted ones when it is unknown
+fn parse_format<T>(sub_m: &ArgMatches, parse: fn(&str) -> Option<T>, accepted: &str) -> T {
+    let format = sub_m.value_of("format").unwrap_or_default();
+    parse(format).unwrap_or_else(|| {
+        eprintln!("unknown format {}, must be {}", format, accepted);
+        process::exit(1);
+    })
+}
+
+fn fix(sub_m: &ArgMatches) -> AsResult<()> {
+    let addr = sub_m.value_of("node").expect("get node err");
+    let mut node = Node::new(addr.as_bytes())?;
+    node.connect()?;
+    let nodes = node.nodes()?;
+    let cluster = Cluster::new(nodes);
+    let fixed = cluster.fix_slots();
+    cluster.fill_slots()?;
+    fixed
+}
+
+fn reshard(sub_m: &ArgMatches) -> AsResult<()> {
+    let addr = sub_m.value_of("node").expect("get node err");
+    let mut node = Node::new(addr.as_bytes())?;
+    node.connect()?;
+    let nodes = node.nodes()?;
+    let cluster = Cluster::new(nodes);
+    let weights = rebalance::parse_weights(sub_m.values_of("weight").into_iter().flatten())
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+    let threshold = clap::value_t!(sub_m.value_of("threshold"), f64).unwrap_or_else(|e| e.exit());
+    let mode = if sub_m.is_present("by-memory") {
+        let sample = clap::value_t!(sub_m.value_of("sample"), usize).unwrap_or_else(|e| e.exit());
+        rebalance::Mode::Memory(sample.max(1))
+    } else {
+        rebalance::Mode::Slots
+    };
+    cluster.reshard(&Rebalance {
+        weights,
+        threshold,
+        use_empty_masters: sub_m.is_present("use-empty-masters"),
+        mode,
+    })
+}
+
+fn report(sub_m: &ArgMatches) -> AsResult<()> {
+    let addr = sub_m.value_of("node").expect("get node err");
+    let output = sub_m.value_of("output").unwrap_or("stdout");
+    let columns = report::parse_columns(sub_m.value_of("column").unwrap_or_default())
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+    let format = parse_format(sub_m, report::Format::parse, "csv or json");
+    let report = Report::new(addr, columns)?;
+    report.write(output, &format)?;
+    Ok(())
+}
+
+fn check(sub_m: &ArgMatches) -> AsResult<()> {
+    let addr = sub_m.value_of("node").expect("get node err");
+    let health = Health::new(addr)?;
+    health.print();
+    if !health.ok() {
+        return Err(Error::State("cluster check fail".to_string()));
+    }
+    Ok(())
+}
+
+fn failover(sub_m: &ArgMatches) -> AsResult<()> {
+    let addr = sub_m.value_of("node").expect("get node err");
+    let mode = if sub_m.is_present("force") {
+        failover::Mode::Force
+    } else if sub_m.is_present("takeover") {
+        failover::Mode::Takeover
+    } else {
+        failover::Mode::Default
+    };
+    let timeout = clap::value_t!(sub_m.value_of("timeout"), u64).unwrap_or_else(|e| e.exit());
+    let failover = Failover::new(addr)?;
+    failover.run(mode, time::Duration::from_secs(timeout))
+}
+
+fn topology(sub_m: &ArgMatches) -> AsResult<()> {
+    match sub_m.subcommand() {
+        ("export", Some(sub_m)) => {
+            let addr = sub_m.value_of("node").expect("get node err");
+            let output = sub_m.value_of("output").unwrap_or("stdout");
+            let format = parse_format(sub_m, topology::Format::parse, "json or yaml");
+            let topology = Topology::new(addr)?;
+            topology.write(output, &format)?;
+            Ok(())
+        }
+        _ => {
+            println!("{}", sub_m.usage());
+            Ok(())
+        }
+    }
+}
+
+fn apply(sub_m: &ArgMatches) -> AsResult<()> {
+    let addr = sub_m.value_of("node").expect("get node err");
+    let file = sub_m.value_of("file").expect("get file err");
+    let desired = apply::Desired::load(file)?;
+    apply::apply(addr, &desired)
+}
+
+fn backup(sub_m: &ArgMatches) -> AsResult<()> {
+    let addr = sub_m.value_of("node").expect("get node err");
+    let output = sub_m.value_of("output").unwrap_or("stdout");
+    let timeout = clap::value_t!(sub_m.value_of("timeout"), u64).unwrap_or_else(|e| e.exit());
+    let mut backup = Backup::new(addr, sub_m.is_present("replicas"))?;
+    if let Some(manifest) = backup.run(time::Duration::from_secs(timeout))? {
+        manifest.write(output)?;
+    }
+    Ok(())
+}
+
+fn rdb(sub_m: &ArgMatches) -> AsResult<()> {
+    match sub_m.subcommand() {
+        ("analyze", Some(sub_m)) => {
+            let file = sub_m.value_of("file").expect("get file err");
+            let output = sub_m.value_of("output").unwrap_or("stdout");
+            let separator = sub_m.value_of("separator").unwrap_or(":");
+            let format = parse_format(sub_m, report::Format::parse, "csv or json");
+            let topology = match sub_m.value_of("topology") {
+                Some(path) => Some(Topology::load(path)?),
+                None => None,
+            };
+            let mut analysis = rdb::Analysis::new(separator, topology.as_ref());
+            analysis.load(file)?;
+            analysis.write(output, &format)?;
+            Ok(())
+        }
+        _ => {
+            println!("{}", sub_m.usage());
+            Ok(())
+        }
+    }
+}
+
+fn bigkeys(sub_m: &ArgMatches) -> AsResult<()> {
+    let addr = sub_m.value_of("node").expect("get node err");
+    let output = sub_m.value_of("output").unwrap_or("stdout");
+    let top = clap::value_t!(sub_m.value_of("top"), usize).unwrap_or_else(|e| e.exit());
+    let count = clap::value_t!(sub_m.value_of("count"), usize).unwrap_or_else(|e| e.exit());
+    let samples = clap::value_t!(sub_m.value_of("samples"), usize).unwrap_or_else(|e| e.exit());
+    let format = parse_format(sub_m, report::Format::parse, "csv or json");
+    let scan = bigkeys::Scan::new(addr, sub_m.is_present("replicas"), top, count, samples)?;
+    scan.run()?.write(output, &format)?;
+    Ok(())
+}
+
+fn hotkeys(sub_m: &ArgMatches) -> AsResult<()> {
+    let addr = sub_m.value_of("node").expect("get node err");
+    let output = sub_m.value_of("output").unwrap_or("stdout");
+    let top = clap::value_t!(sub_m.value_of("top"), usize).unwrap_or_else(|e| e.exit());
+    let count = clap::value_t!(sub_m.value_of("count"), usize).unwrap_or_else(|e| e.exit());
+    let format = parse_format(sub_m, report::Format::parse, "csv or json");
+    let scan = hotkeys::Scan::new(addr, top, count)?;
+    let mode = if sub_m.is_present("monitor") {
+        let seconds = clap::value_t!(sub_m.value_of("monitor"), u64).unwrap_or_else(|e| e.exit());
+        eprintln!(
+            "MONITOR every master for {}s, it slows them down while it runs",
+            seconds
+        );
+        hotkeys::Mode::Monitor(time::Duration::from_secs(seconds))
+    } else {
+        let without_lfu = scan.without_lfu()?;
+        if !without_lfu.is_empty() {
+            for (addr, policy) in &without_lfu {
+                eprintln!("{} has maxmemory-policy {}, no LFU counter", addr, policy);
             }
-            _ => println!("err"),
+            return Err(Error::State(
+                "OBJECT FREQ needs an lfu maxmemory-policy, use --monitor <seconds> to sample commands instead"
+                    .to_string(),
+            ));
         }
-        return;
+        hotkeys::Mode::Lfu
+    };
+    scan.run(&mode)?.write(output, &format)?;
+    Ok(())
+}
+
+fn slots(sub_m: &ArgMatches) -> AsResult<()> {
+    let addr = sub_m.value_of("node").expect("get node err");
+    let output = sub_m.value_of("output").unwrap_or("stdout");
+    let cell = clap::value_t!(sub_m.value_of("cell"), usize).unwrap_or_else(|e| e.exit());
+    if cell == 0 {
+        eprintln!("--cell must be greater than 0");
+        process::exit(1);
     }
+    let format = parse_format(sub_m, slots::Format::parse, "heatmap, csv or json");
+    let slots = slots::Slots::new(addr)?;
+    slots.write(output, &format, cell)?;
+    Ok(())
+}
 
-    if let Some(sub_m) = matches.subcommand_matches("fix") {
-        let addr = sub_m.value_of("node").expect("get node err");
-        let mut node = Node::new(addr.as_bytes()).unwrap();
-        node.connect();
-        let nodes = node.nodes();
-        let cluster = Cluster::new(nodes);
-        cluster.fix_slots();
-        cluster.fill_slots();
-        return;
+fn exporter(sub_m: &ArgMatches) -> AsResult<()> {
+    let addr = sub_m.value_of("node").expect("get node err");
+    let listen = sub_m.value_of("listen").expect("get listen err");
+    let interval = clap::value_t!(sub_m.value_of("interval"), u64).unwrap_or_else(|e| e.exit());
+    if interval == 0 {
+        eprintln!("--interval must be greater than 0");
+        process::exit(1);
     }
+    exporter::serve(listen, addr, time::Duration::from_secs(interval))
+}
 
-    if let Some(sub_m) = matches.subcommand_matches("reshard") {
-        let addr = sub_m.value_of("node").expect("get node err");
-        let mut node = Node::new(addr.as_bytes()).unwrap();
-        node.connect();
-        let nodes = node.nodes();
-        let cluster = Cluster::new(nodes);
-        cluster.reshard();
-        return;
+fn top(sub_m: &ArgMatches) -> AsResult<()> {
+    let addr = sub_m.value_of("node").expect("get node err");
+    let interval = clap::value_t!(sub_m.value_of("interval"), u64).unwrap_or_else(|e| e.exit());
+    if interval == 0 {
+        eprintln!("--interval must be greater than 0");
+        process::exit(1);
     }
+    top::run(addr, time::Duration::from_secs(interval))
+}
 
-    println!("{}", matches.usage())
+fn resume(sub_m: &ArgMatches) -> AsResult<()> {
+    let path = sub_m.value_of("file").expect("get file err");
+    journal::resume(path)
 }
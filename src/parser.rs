@@ -0,0 +1,334 @@
+use addr::Addr;
+use cluster::{AsResult, Error};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use util::CLUSTER_SLOTS;
+
+#[test]
+fn test_parse_redis3() {
+    let output = "\
+07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004 slave e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 0 1426238317239 4 connected
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:30002 master - 0 1426238316232 2 connected 5461-10922
+292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f 127.0.0.1:30003 master - 0 1426238318243 3 connected 10923-16383
+6ec23923021cf3ffec47632106199cb7f496ce01 127.0.0.1:30005 slave 67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 0 1426238316232 5 connected
+824fe116063bc5fcf9f4ffd895bc17aee7731ac3 127.0.0.1:30006 slave 292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f 0 1426238317741 6 connected
+e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:30001 myself,master - 0 0 1 connected 0-5460
+";
+    let entries = parse_nodes(output).unwrap();
+    assert_eq!(entries.len(), 6);
+    let slave = &entries[0];
+    assert_eq!(slave.id, "07c37dfeb235213a872192d90877d0cd55635b91");
+    assert_eq!(slave.addr.to_string(), "127.0.0.1:30004");
+    assert_eq!(slave.addr.cport, None);
+    assert_eq!(slave.addr.hostname, None);
+    assert!(slave.has(&Flag::Slave));
+    assert_eq!(
+        slave.master_id,
+        Some("e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca".to_string())
+    );
+    assert_eq!(slave.ping_sent, 0);
+    assert_eq!(slave.pong_recv, 1426238317239);
+    assert_eq!(slave.config_epoch, 4);
+    assert_eq!(slave.link_state, LinkState::Connected);
+    assert!(slave.slots.is_empty());
+
+    let myself = &entries[5];
+    assert!(myself.has(&Flag::Myself));
+    assert!(myself.has(&Flag::Master));
+    assert_eq!(myself.master_id, None);
+    assert_eq!(myself.slots, vec![(0, 5460)]);
+    assert_eq!(myself.slot_count(), 5461);
+}
+
+#[test]
+fn test_parse_redis4_open_slots() {
+    let output = "\
+e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:30001@40001 myself,master - 0 1426238316232 1 connected 0-5460 [93-<-292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f] [1002->-67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1]
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:30002@40002 master - 0 1426238316232 2 connected 5461-10922 16000 16002-16003
+";
+    let entries = parse_nodes(output).unwrap();
+    let myself = &entries[0];
+    assert_eq!(myself.addr.to_string(), "127.0.0.1:30001");
+    assert_eq!(myself.addr.cport, Some(40001));
+    assert_eq!(myself.slots, vec![(0, 5460)]);
+    assert_eq!(
+        myself.importing.get(&93).map(|x| x.as_str()),
+        Some("292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f")
+    );
+    assert_eq!(
+        myself.migrating.get(&1002).map(|x| x.as_str()),
+        Some("67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1")
+    );
+    let other = &entries[1];
+    assert_eq!(
+        other.slots,
+        vec![(5461, 10922), (16000, 16000), (16002, 16003)]
+    );
+    assert_eq!(other.slot_list().len(), 5465);
+}
+
+#[test]
+fn test_open_slots_from() {
+    let mut entries = parse_nodes(
+        "\
+aaaa 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-99
+bbbb 127.0.0.1:7001@17001 master - 0 0 2 connected 100-199
+",
+    )
+    .unwrap();
+    let own = parse_nodes(
+        "\
+aaaa 127.0.0.1:7000@17000 master - 0 0 1 connected 0-99
+bbbb 127.0.0.1:7001@17001 myself,master - 0 0 2 connected 100-199 [100->-aaaa]
+",
+    )
+    .unwrap();
+    entries[0].open_slots_from(own.clone());
+    assert!(entries[0].migrating.is_empty());
+    entries[1].open_slots_from(own);
+    assert_eq!(
+        entries[1].migrating.get(&100).map(|x| x.as_str()),
+        Some("aaaa")
+    );
+}
+
+#[test]
+fn test_parse_redis5_failure_flags() {
+    let output = "\
+a2c8e4b58ae4b7ef0c7ba2d8b0ea5d1f96b07a93 10.0.0.2:7000@17000 master,fail - 1548135000000 1548134990000 7 disconnected
+b1e0a5ac7a8f2dc1e4c8b8f0f1a53bc3c2b53e12 10.0.0.3:7000@17000 slave,fail? a2c8e4b58ae4b7ef0c7ba2d8b0ea5d1f96b07a93 1548135000000 1548134990000 7 connected
+c3f5d3e61da1ea0b5d4c8c3f6c8f4a2b2f2c1a0d :0@0 master,fail,noaddr - 1548135000000 1548134990000 3 disconnected
+d4a6e4f72eb2fb1c6e5d9d4a7d9a5b3c3a3d2b1e 10.0.0.5:7000@17000 handshake - 1548135000000 0 0 disconnected
+e5b7f5a83fc3ac2d7f6eae5b8eab6c4d4b4e3c2f 10.0.0.6:7000@17000 myself,noflags - 0 0 0 connected
+";
+    let entries = parse_nodes(output).unwrap();
+    assert!(entries[0].has(&Flag::Fail));
+    assert!(entries[0].is_failing());
+    assert_eq!(entries[0].link_state, LinkState::Disconnected);
+    assert_eq!(entries[0].ping_sent, 1548135000000);
+    assert!(entries[1].has(&Flag::PFail));
+    assert!(entries[1].is_failing());
+    assert_eq!(entries[2].addr.to_string(), ":0");
+    assert_eq!(entries[2].addr.cport, Some(0));
+    assert!(entries[2].has(&Flag::NoAddr));
+    assert!(entries[3].has(&Flag::Handshake));
+    assert!(!entries[3].has(&Flag::Master));
+    assert!(entries[4].has(&Flag::NoFlags));
+    assert!(!entries[4].is_failing());
+}
+
+#[test]
+fn test_parse_redis7_hostname() {
+    let output = "\
+4f1c2a43ab7d4f0b8a2c8b0e9c6ad3a8a1b2c3d4 10.0.0.1:6379@16379,redis-0.redis.svc myself,master - 0 1671000000000 8 connected 0-8191
+5a2d3b54bc8e5a1c9b3d9c1fad7be4b9b2c3d4e5 10.0.0.2:6379@16379,redis-1.redis.svc slave,nofailover 4f1c2a43ab7d4f0b8a2c8b0e9c6ad3a8a1b2c3d4 0 1671000000123 8 connected
+6b3e4c65cd9f6b2dac4eac2abe8cf5cac3d4e5f6 10.0.0.3:6379@16379,,tls-port=0,shard-id=9a8b7c6d5e4f3a2b1c0d9e8f7a6b5c4d3e2f1a0b master - 0 1671000000456 9 connected 8192-16383
+";
+    let entries = parse_nodes(output).unwrap();
+    assert_eq!(entries[0].addr.to_string(), "10.0.0.1:6379");
+    assert_eq!(entries[0].addr.cport, Some(16379));
+    assert_eq!(
+        entries[0].addr.hostname,
+        Some("redis-0.redis.svc".to_string())
+    );
+    assert!(entries[1].has(&Flag::NoFailover));
+    assert!(entries[1].has(&Flag::Slave));
+    assert_eq!(entries[2].addr.hostname, None);
+    assert_eq!(entries[2].addr.cport, Some(16379));
+    assert_eq!(entries[2].slots, vec![(8192, 16383)]);
+}
+
+#[test]
+fn test_parse_bad_line() {
+    assert!(parse_nodes("07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004 slave").is_err());
+    assert!(parse_nodes(
+        "07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004 master - 0 0 1 connected 10-x"
+    )
+    .is_err());
+    assert!(parse_nodes(
+        "07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004 master - 0 0 1 connected 20000"
+    )
+    .is_err());
+    assert!(parse_nodes(
+        "07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004@abc master - 0 0 1 connected"
+    )
+    .is_err());
+    assert_eq!(parse_nodes("\n").unwrap().len(), 0);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Flag {
+    Myself,
+    Master,
+    Slave,
+    PFail,
+    Fail,
+    Handshake,
+    NoAddr,
+    NoFailover,
+    NoFlags,
+    Unknown(String),
+}
+
+impl Flag {
+    fn parse(flag: &str) -> Flag {
+        match flag {
+            "myself" => Flag::Myself,
+            "master" => Flag::Master,
+            "slave" => Flag::Slave,
+            "fail?" => Flag::PFail,
+            "fail" => Flag::Fail,
+            "handshake" => Flag::Handshake,
+            "noaddr" => Flag::NoAddr,
+            "nofailover" => Flag::NoFailover,
+            "noflags" => Flag::NoFlags,
+            other => Flag::Unknown(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = match self {
+            Flag::Myself => "myself",
+            Flag::Master => "master",
+            Flag::Slave => "slave",
+            Flag::PFail => "fail?",
+            Flag::Fail => "fail",
+            Flag::Handshake => "handshake",
+            Flag::NoAddr => "noaddr",
+            Flag::NoFailover => "nofailover",
+            Flag::NoFlags => "noflags",
+            Flag::Unknown(flag) => flag,
+        };
+        f.write_str(flag)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkState {
+    Connected,
+    Disconnected,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeEntry {
+    pub id: String,
+    pub addr: Addr,
+    pub flags: HashSet<Flag>,
+    pub master_id: Option<String>,
+    pub ping_sent: u64,
+    pub pong_recv: u64,
+    pub config_epoch: u64,
+    pub link_state: LinkState,
+    pub slots: Vec<(usize, usize)>,
+    pub migrating: HashMap<usize, String>,
+    pub importing: HashMap<usize, String>,
+}
+
+impl NodeEntry {
+    pub fn parse(line: &str) -> AsResult<NodeEntry> {
+        let bad_line = || Error::Protocol(format!("bad CLUSTER NODES line {:?}", line));
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 8 {
+            return Err(bad_line());
+        }
+
+        let addr = Addr::parse(fields[1]).map_err(|_| bad_line())?;
+        let flags = fields[2].split(',').map(Flag::parse).collect();
+        let master_id = if fields[3] == "-" {
+            None
+        } else {
+            Some(fields[3].to_string())
+        };
+        let parse_num = |field: &str| field.parse::<u64>().map_err(|_| bad_line());
+        let link_state = match fields[7] {
+            "connected" => LinkState::Connected,
+            "disconnected" => LinkState::Disconnected,
+            _ => return Err(bad_line()),
+        };
+
+        let mut entry = NodeEntry {
+            id: fields[0].to_string(),
+            addr,
+            flags,
+            master_id,
+            ping_sent: parse_num(fields[4])?,
+            pong_recv: parse_num(fields[5])?,
+            config_epoch: parse_num(fields[6])?,
+            link_state,
+            slots: vec![],
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+        };
+
+        let parse_slot = |slot: &str| match slot.parse::<usize>() {
+            Ok(slot) if slot < CLUSTER_SLOTS => Ok(slot),
+            _ => Err(bad_line()),
+        };
+        for content in &fields[8..] {
+            if content.starts_with('[') {
+                let open = content.trim_matches(|c| c == '[' || c == ']');
+                if let Some(idx) = open.find("->-") {
+                    let slot = parse_slot(&open[..idx])?;
+                    entry.migrating.insert(slot, open[idx + 3..].to_string());
+                } else if let Some(idx) = open.find("-<-") {
+                    let slot = parse_slot(&open[..idx])?;
+                    entry.importing.insert(slot, open[idx + 3..].to_string());
+                } else {
+                    return Err(bad_line());
+                }
+            } else {
+                let mut scope = content.splitn(2, '-');
+                let start = parse_slot(scope.next().unwrap_or_default())?;
+                let end = match scope.next() {
+                    Some(end) => parse_slot(end)?,
+                    None => start,
+                };
+                if end < start {
+                    return Err(bad_line());
+                }
+                entry.slots.push((start, end));
+            }
+        }
+        Ok(entry)
+    }
+
+    pub fn has(&self, flag: &Flag) -> bool {
+        self.flags.contains(flag)
+    }
+
+    pub fn is_failing(&self) -> bool {
+        self.has(&Flag::Fail) || self.has(&Flag::PFail)
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.slots.iter().map(|&(start, end)| end - start + 1).sum()
+    }
+
+    // only the myself line of a node shows its open slots, take them from its own view
+    pub fn open_slots_from(&mut self, own: Vec<NodeEntry>) {
+        if let Some(own) = own
+            .into_iter()
+            .find(|x| x.has(&Flag::Myself) && x.id == self.id)
+        {
+            self.migrating = own.migrating;
+            self.importing = own.importing;
+        }
+    }
+
+    pub fn slot_list(&self) -> Vec<usize> {
+        self.slots
+            .iter()
+            .flat_map(|&(start, end)| start..=end)
+            .collect()
+    }
+}
+
+pub fn parse_nodes(output: &str) -> AsResult<Vec<NodeEntry>> {
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(NodeEntry::parse)
+        .collect()
+}
@@ -0,0 +1,643 @@
+use cluster::{AsResult, Error};
+use report::{self, Format};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use topology::Topology;
+use util;
+
+#[cfg(test)]
+fn string(out: &mut Vec<u8>, data: &[u8]) {
+    out.push(data.len() as u8);
+    out.extend_from_slice(data);
+}
+
+#[cfg(test)]
+fn sample() -> Vec<u8> {
+    let mut rdb = b"REDIS0010".to_vec();
+    rdb.push(0xfa);
+    string(&mut rdb, b"ctime");
+    rdb.extend_from_slice(&[0xc2, 0x00, 0x00, 0x00, 0x10]);
+    rdb.push(0xf5);
+    string(&mut rdb, b"#!lua name=lib\nredis.register_function('f', f)");
+    rdb.extend_from_slice(&[0xfe, 0x00, 0xfb, 0x05, 0x01]);
+    // user:1 = "alice", expires one minute after ctime
+    rdb.push(0xfc);
+    rdb.extend_from_slice(&(0x1000_0000u64 * 1000 + 60_000).to_le_bytes());
+    rdb.push(0);
+    string(&mut rdb, b"user:1");
+    string(&mut rdb, b"alice");
+    // user:2 = 12345 as an int encoded string
+    rdb.push(0);
+    string(&mut rdb, b"user:2");
+    rdb.extend_from_slice(&[0xc1, 0x39, 0x30]);
+    // queue = [a, b]
+    rdb.push(1);
+    string(&mut rdb, b"queue");
+    rdb.push(2);
+    string(&mut rdb, b"a");
+    string(&mut rdb, b"b");
+    // rank = {a: 1.0}
+    rdb.push(5);
+    string(&mut rdb, b"rank");
+    rdb.push(1);
+    string(&mut rdb, b"a");
+    rdb.extend_from_slice(&1.0f64.to_le_bytes());
+    // lzf compressed "aaaaaaaaaa" as value of user:3
+    rdb.push(0);
+    string(&mut rdb, b"user:3");
+    rdb.extend_from_slice(&[0xc3, 0x05, 0x0a, 0x00, b'a', 0xe0, 0x00, 0x00]);
+    rdb.push(0xff);
+    rdb.extend_from_slice(&[0; 8]);
+    rdb
+}
+
+#[test]
+fn test_parse_rdb() {
+    let mut entries = Vec::new();
+    let mut aux = Vec::new();
+    parse(&mut &sample()[..], |event| match event {
+        Event::Aux(key, value) => aux.push((key, value)),
+        Event::Entry(entry) => entries.push(entry),
+    })
+    .unwrap();
+    assert_eq!(aux, vec![("ctime".to_string(), "268435456".to_string())]);
+    let keys: Vec<&[u8]> = entries.iter().map(|x| &x.key[..]).collect();
+    assert_eq!(
+        keys,
+        vec![&b"user:1"[..], b"user:2", b"queue", b"rank", b"user:3"]
+    );
+    let kinds: Vec<&str> = entries.iter().map(|x| x.kind).collect();
+    assert_eq!(kinds, vec!["string", "string", "list", "zset", "string"]);
+    assert_eq!(entries[0].expire, Some(0x1000_0000u64 * 1000 + 60_000));
+    assert_eq!(entries[1].expire, None);
+    assert_eq!(entries[0].bytes, 1 + 7 + 6);
+
+    // a length far beyond the end of the file
+    let mut huge = b"REDIS0010".to_vec();
+    huge.extend_from_slice(&[0, 0x80, 0xff, 0xff, 0xff, 0xff]);
+    match parse(&mut &huge[..], |_| {}) {
+        Err(Error::Protocol(_)) => {}
+        other => panic!("expect a protocol error, got {:?}", other),
+    }
+
+    let mut pre_ga = b"REDIS0010".to_vec();
+    pre_ga.push(0xf6);
+    string(&mut pre_ga, b"lib");
+    assert!(parse(&mut &pre_ga[..], |_| {}).is_err());
+}
+
+#[test]
+fn test_lzf() {
+    assert_eq!(
+        lzf(&[0x00, b'a', 0xe0, 0x00, 0x00], 10).unwrap(),
+        b"aaaaaaaaaa"
+    );
+    assert!(lzf(&[0x00, b'a', 0xe0, 0x00, 0x05], 10).is_err());
+}
+
+#[test]
+fn test_analysis() {
+    let mut analysis = Analysis::new(":", None);
+    analysis.read(&mut &sample()[..]).unwrap();
+    assert_eq!(analysis.total.keys, 5);
+    assert_eq!(analysis.total.types["string"], 3);
+    assert_eq!(analysis.total.ttl["<1h"], 1);
+    assert_eq!(analysis.total.ttl["none"], 4);
+    assert_eq!(analysis.prefixes["user"].keys, 3);
+    assert_eq!(analysis.prefixes[NO_PREFIX].keys, 2);
+    assert_eq!(analysis.slots[&util::key_slot(b"rank")].keys, 1);
+}
+
+const TYPES: &[&str] = &["string", "list", "set", "zset", "hash", "stream", "module"];
+const NO_PREFIX: &str = "(no prefix)";
+const TTLS: &[&str] = &["none", "expired", "<1h", "<1d", "<7d", ">=7d"];
+
+#[derive(Debug)]
+pub struct Entry {
+    pub key: Vec<u8>,
+    pub kind: &'static str,
+    pub expire: Option<u64>,
+    pub bytes: u64,
+}
+
+pub enum Event {
+    Aux(String, String),
+    Entry(Entry),
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    pub keys: u64,
+    pub bytes: u64,
+    pub types: BTreeMap<String, u64>,
+    pub ttl: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    fn add(&mut self, entry: &Entry, ttl: &str) {
+        self.keys += 1;
+        self.bytes += entry.bytes;
+        *self.types.entry(entry.kind.to_string()).or_insert(0) += 1;
+        *self.ttl.entry(ttl.to_string()).or_insert(0) += 1;
+    }
+
+    fn row(&self, scope: &str, name: &str) -> Vec<String> {
+        let mut row = vec![
+            scope.to_string(),
+            name.to_string(),
+            self.keys.to_string(),
+            self.bytes.to_string(),
+        ];
+        row.extend(
+            TYPES
+                .iter()
+                .map(|x| self.types.get(*x).unwrap_or(&0).to_string()),
+        );
+        row.extend(
+            TTLS.iter()
+                .map(|x| self.ttl.get(*x).unwrap_or(&0).to_string()),
+        );
+        row
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Analysis {
+    pub total: Stats,
+    pub slots: BTreeMap<usize, Stats>,
+    pub prefixes: BTreeMap<String, Stats>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub nodes: BTreeMap<String, Stats>,
+    #[serde(skip)]
+    separator: String,
+    #[serde(skip)]
+    owners: HashMap<usize, String>,
+    #[serde(skip)]
+    now: u64,
+}
+
+impl Analysis {
+    pub fn new(separator: &str, topology: Option<&Topology>) -> Analysis {
+        let mut owners = HashMap::new();
+        for node in topology
+            .iter()
+            .flat_map(|x| &x.nodes)
+            .filter(|x| x.role == "master")
+        {
+            for (start, end) in node.slots.iter().filter_map(|x| util::parse_slot_range(x)) {
+                for slot in start..=end {
+                    owners.insert(slot, node.addr.clone());
+                }
+            }
+        }
+        Analysis {
+            total: Stats::default(),
+            slots: BTreeMap::new(),
+            prefixes: BTreeMap::new(),
+            nodes: BTreeMap::new(),
+            separator: separator.to_string(),
+            owners,
+            now: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_millis() as u64)
+                .unwrap_or(0),
+        }
+    }
+
+    pub fn load(&mut self, path: &str) -> AsResult<()> {
+        let mut reader = BufReader::new(File::open(path)?);
+        self.read(&mut reader)
+    }
+
+    fn read<R: Read>(&mut self, reader: &mut R) -> AsResult<()> {
+        parse(reader, |event| match event {
+            // ttls are relative to the moment the rdb was written when it is known
+            Event::Aux(key, value) => {
+                if key == "ctime" {
+                    if let Ok(ctime) = value.parse::<u64>() {
+                        self.now = ctime * 1000;
+                    }
+                }
+            }
+            Event::Entry(entry) => self.add(&entry),
+        })
+    }
+
+    fn add(&mut self, entry: &Entry) {
+        let ttl = match entry.expire {
+            None => "none",
+            Some(at) if at <= self.now => "expired",
+            Some(at) if at - self.now < 3_600_000 => "<1h",
+            Some(at) if at - self.now < 86_400_000 => "<1d",
+            Some(at) if at - self.now < 7 * 86_400_000 => "<7d",
+            Some(_) => ">=7d",
+        };
+        let slot = util::key_slot(&entry.key);
+        let key = String::from_utf8_lossy(&entry.key);
+        let prefix = match key.find(&*self.separator) {
+            Some(idx) if !self.separator.is_empty() => &key[..idx],
+            _ => NO_PREFIX,
+        };
+        self.total.add(entry, ttl);
+        self.slots.entry(slot).or_default().add(entry, ttl);
+        self.prefixes
+            .entry(prefix.to_string())
+            .or_default()
+            .add(entry, ttl);
+        if let Some(owner) = self.owners.get(&slot) {
+            self.nodes.entry(owner.clone()).or_default().add(entry, ttl);
+        }
+    }
+
+    pub fn write(&self, output: &str, format: &Format) -> io::Result<()> {
+        util::write_output(output, |out| self.write_to(out, format))
+    }
+
+    fn write_to<W: Write>(&self, out: &mut W, format: &Format) -> io::Result<()> {
+        match format {
+            Format::Csv => {
+                let mut header = vec!["scope", "name", "keys", "bytes"];
+                header.extend(TYPES);
+                header.extend(TTLS);
+                writeln!(out, "{}", header.join(","))?;
+                let mut rows = vec![self.total.row("total", "")];
+                rows.extend(self.nodes.iter().map(|(k, v)| v.row("node", k)));
+                rows.extend(
+                    self.slots
+                        .iter()
+                        .map(|(k, v)| v.row("slot", &k.to_string())),
+                );
+                rows.extend(self.prefixes.iter().map(|(k, v)| v.row("prefix", k)));
+                for row in rows {
+                    let line: Vec<String> = row.iter().map(|x| report::csv_escape(x)).collect();
+                    writeln!(out, "{}", line.join(","))?;
+                }
+            }
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn bad(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct Reader<'a, R: Read> {
+    inner: &'a mut R,
+    pos: u64,
+}
+
+enum Length {
+    Len(u64),
+    Encoded(u8),
+}
+
+impl<'a, R: Read> Reader<'a, R> {
+    // n comes from the file, so only what is really there gets allocated
+    fn bytes(&mut self, n: usize) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        (&mut *self.inner).take(n as u64).read_to_end(&mut buf)?;
+        self.pos += buf.len() as u64;
+        if buf.len() < n {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        Ok(buf)
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn skip(&mut self, n: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut (&mut *self.inner).take(n), &mut io::sink())?;
+        self.pos += skipped;
+        if skipped < n {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        Ok(())
+    }
+
+    fn length(&mut self) -> io::Result<Length> {
+        let first = self.byte()?;
+        Ok(match first >> 6 {
+            0 => Length::Len(u64::from(first & 0x3f)),
+            1 => Length::Len((u64::from(first & 0x3f) << 8) | u64::from(self.byte()?)),
+            2 => match first {
+                0x80 => {
+                    let buf = self.bytes(4)?;
+                    Length::Len(u64::from(u32::from_be_bytes([
+                        buf[0], buf[1], buf[2], buf[3],
+                    ])))
+                }
+                0x81 => {
+                    let mut buf = [0; 8];
+                    buf.copy_from_slice(&self.bytes(8)?);
+                    Length::Len(u64::from_be_bytes(buf))
+                }
+                _ => return Err(bad(format!("bad length byte {:#x}", first))),
+            },
+            _ => Length::Encoded(first & 0x3f),
+        })
+    }
+
+    fn len(&mut self) -> io::Result<u64> {
+        match self.length()? {
+            Length::Len(len) => Ok(len),
+            Length::Encoded(enc) => Err(bad(format!("unexpected string encoding {}", enc))),
+        }
+    }
+
+    fn string(&mut self) -> io::Result<Vec<u8>> {
+        match self.length()? {
+            Length::Len(len) => self.bytes(len as usize),
+            Length::Encoded(0) => Ok((self.byte()? as i8).to_string().into_bytes()),
+            Length::Encoded(1) => {
+                let buf = self.bytes(2)?;
+                Ok(i16::from_le_bytes([buf[0], buf[1]])
+                    .to_string()
+                    .into_bytes())
+            }
+            Length::Encoded(2) => {
+                let buf = self.bytes(4)?;
+                Ok(i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])
+                    .to_string()
+                    .into_bytes())
+            }
+            Length::Encoded(3) => {
+                let clen = self.len()?;
+                let len = self.len()?;
+                let data = self.bytes(clen as usize)?;
+                lzf(&data, len as usize)
+            }
+            Length::Encoded(enc) => Err(bad(format!("unknown string encoding {}", enc))),
+        }
+    }
+
+    fn skip_string(&mut self) -> io::Result<()> {
+        match self.length()? {
+            Length::Len(len) => self.skip(len),
+            Length::Encoded(0) => self.skip(1),
+            Length::Encoded(1) => self.skip(2),
+            Length::Encoded(2) => self.skip(4),
+            Length::Encoded(3) => {
+                let clen = self.len()?;
+                self.len()?;
+                self.skip(clen)
+            }
+            Length::Encoded(enc) => Err(bad(format!("unknown string encoding {}", enc))),
+        }
+    }
+
+    fn skip_strings(&mut self, n: u64) -> io::Result<()> {
+        for _ in 0..n {
+            self.skip_string()?;
+        }
+        Ok(())
+    }
+
+    // module values and module aux data are a list of typed fields ending with 0
+    fn skip_module(&mut self) -> io::Result<()> {
+        loop {
+            match self.len()? {
+                0 => return Ok(()),
+                1 | 2 => {
+                    self.len()?;
+                }
+                3 => self.skip(4)?,
+                4 => self.skip(8)?,
+                5 => self.skip_string()?,
+                op => return Err(bad(format!("unknown module opcode {}", op))),
+            }
+        }
+    }
+
+    fn skip_stream(&mut self, kind: u8) -> io::Result<()> {
+        let nodes = self.len()?;
+        self.skip_strings(nodes * 2)?;
+        // length, last id
+        self.skip_lens(3)?;
+        if kind >= 19 {
+            // first id, max deleted id, entries added
+            self.skip_lens(5)?;
+        }
+        let groups = self.len()?;
+        for _ in 0..groups {
+            self.skip_string()?;
+            self.skip_lens(2)?;
+            if kind >= 19 {
+                self.len()?;
+            }
+            let pending = self.len()?;
+            for _ in 0..pending {
+                self.skip(16 + 8)?;
+                self.len()?;
+            }
+            let consumers = self.len()?;
+            for _ in 0..consumers {
+                self.skip_string()?;
+                self.skip(if kind >= 21 { 16 } else { 8 })?;
+                let pending = self.len()?;
+                self.skip(pending * 16)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_lens(&mut self, n: usize) -> io::Result<()> {
+        for _ in 0..n {
+            self.len()?;
+        }
+        Ok(())
+    }
+
+    fn skip_value(&mut self, kind: u8) -> io::Result<&'static str> {
+        match kind {
+            0 => self.skip_string()?,
+            1 | 2 => {
+                let n = self.len()?;
+                self.skip_strings(n)?;
+            }
+            3 => {
+                let n = self.len()?;
+                for _ in 0..n {
+                    self.skip_string()?;
+                    let score = self.byte()?;
+                    if score < 253 {
+                        self.skip(u64::from(score))?;
+                    }
+                }
+            }
+            4 => {
+                let n = self.len()?;
+                self.skip_strings(n * 2)?;
+            }
+            5 => {
+                let n = self.len()?;
+                for _ in 0..n {
+                    self.skip_string()?;
+                    self.skip(8)?;
+                }
+            }
+            7 => {
+                self.len()?;
+                self.skip_module()?;
+            }
+            9..=13 | 16 | 17 | 20 => self.skip_string()?,
+            14 => {
+                let n = self.len()?;
+                self.skip_strings(n)?;
+            }
+            18 => {
+                let n = self.len()?;
+                for _ in 0..n {
+                    self.len()?;
+                    self.skip_string()?;
+                }
+            }
+            15 | 19 | 21 => self.skip_stream(kind)?,
+            24 => {
+                self.skip(8)?;
+                let n = self.len()?;
+                for _ in 0..n {
+                    self.len()?;
+                    self.skip_strings(2)?;
+                }
+            }
+            25 => {
+                self.skip(8)?;
+                self.skip_string()?;
+            }
+            _ => return Err(bad(format!("unsupported value type {}", kind))),
+        }
+        Ok(match kind {
+            0 => "string",
+            1 | 10 | 14 | 18 => "list",
+            2 | 11 | 20 => "set",
+            3 | 5 | 12 | 17 => "zset",
+            4 | 9 | 13 | 16 | 24 | 25 => "hash",
+            15 | 19 | 21 => "stream",
+            _ => "module",
+        })
+    }
+}
+
+pub fn parse<R: Read, F: FnMut(Event)>(reader: &mut R, f: F) -> AsResult<()> {
+    events(reader, f).map_err(|err| match err.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+            Error::Protocol(format!("corrupt rdb: {}", err))
+        }
+        _ => Error::Io(err),
+    })
+}
+
+fn events<R: Read, F: FnMut(Event)>(reader: &mut R, mut f: F) -> io::Result<()> {
+    let mut rdb = Reader {
+        inner: reader,
+        pos: 0,
+    };
+    let magic = rdb.bytes(9)?;
+    if &magic[..5] != b"REDIS" {
+        return Err(bad("not an rdb file".to_string()));
+    }
+    let mut expire = None;
+    loop {
+        let start = rdb.pos;
+        let op = rdb.byte()?;
+        match op {
+            0xff => return Ok(()),
+            0xfe => {
+                rdb.len()?;
+            }
+            0xfd => {
+                let buf = rdb.bytes(4)?;
+                let secs = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+                expire = Some(u64::from(secs) * 1000);
+            }
+            0xfc => {
+                let mut buf = [0; 8];
+                buf.copy_from_slice(&rdb.bytes(8)?);
+                expire = Some(u64::from_le_bytes(buf));
+            }
+            0xfb => rdb.skip_lens(2)?,
+            0xfa => {
+                let key = rdb.string()?;
+                let value = rdb.string()?;
+                f(Event::Aux(
+                    String::from_utf8_lossy(&key).into_owned(),
+                    String::from_utf8_lossy(&value).into_owned(),
+                ));
+            }
+            0xf9 => rdb.skip(1)?,
+            0xf8 => {
+                rdb.len()?;
+            }
+            0xf7 => {
+                rdb.len()?;
+                rdb.skip_module()?;
+            }
+            // a function library, its code is a single string
+            0xf5 => rdb.skip_string()?,
+            0xf6 => {
+                return Err(bad(
+                    "functions of a redis 7.0 release candidate are not supported".to_string(),
+                ))
+            }
+            0xf4 => rdb.skip_lens(3)?,
+            kind if kind < 0xf0 => {
+                let key = rdb.string()?;
+                let kind = rdb.skip_value(kind)?;
+                f(Event::Entry(Entry {
+                    key,
+                    kind,
+                    expire: expire.take(),
+                    bytes: rdb.pos - start,
+                }));
+            }
+            op => return Err(bad(format!("unsupported rdb opcode {:#x}", op))),
+        }
+    }
+}
+
+fn lzf(input: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    let corrupt = || bad("corrupt lzf string".to_string());
+    let mut out: Vec<u8> = Vec::with_capacity(input.len());
+    let mut idx = 0;
+    while idx < input.len() {
+        if out.len() > len {
+            return Err(corrupt());
+        }
+        let ctrl = input[idx] as usize;
+        idx += 1;
+        if ctrl < 32 {
+            let literal = input.get(idx..idx + ctrl + 1).ok_or_else(corrupt)?;
+            out.extend_from_slice(literal);
+            idx += ctrl + 1;
+            continue;
+        }
+        let mut run = ctrl >> 5;
+        if run == 7 {
+            run += *input.get(idx).ok_or_else(corrupt)? as usize;
+            idx += 1;
+        }
+        let back = ((ctrl & 0x1f) << 8) + *input.get(idx).ok_or_else(corrupt)? as usize + 1;
+        idx += 1;
+        if back > out.len() {
+            return Err(corrupt());
+        }
+        let start = out.len() - back;
+        for offset in 0..run + 2 {
+            let byte = out[start + offset];
+            out.push(byte);
+        }
+    }
+    if out.len() != len {
+        return Err(corrupt());
+    }
+    Ok(out)
+}
@@ -0,0 +1,372 @@
+use cluster::{AsResult, Error, Node};
+use schedule::Move;
+use slots;
+use std::collections::{BTreeMap, HashMap};
+use util::CLUSTER_SLOTS;
+
+#[test]
+fn test_parse_weights() {
+    let weights = parse_weights(vec!["127.0.0.1:7000=2", "abcd=0.5"].into_iter()).unwrap();
+    assert_eq!(weights.get("127.0.0.1:7000"), Some(&2.0));
+    assert_eq!(weights.get("abcd"), Some(&0.5));
+    assert!(parse_weights(vec!["127.0.0.1:7000"].into_iter()).is_err());
+    assert!(parse_weights(vec!["127.0.0.1:7000=x"].into_iter()).is_err());
+    assert!(parse_weights(vec!["127.0.0.1:7000=-1"].into_iter()).is_err());
+}
+
+#[test]
+fn test_balance_equal() {
+    let pairs = balance(&[16384, 0, 0], &[1.0, 1.0, 1.0], 2.0).unwrap();
+    let moved: usize = pairs.iter().map(|x| x.2).sum();
+    assert_eq!(moved, 16384 - 5461);
+    assert!(pairs.iter().all(|x| x.0 == 0));
+    assert!(balance(&[5462, 5461, 5461], &[1.0, 1.0, 1.0], 2.0).is_none());
+}
+
+#[test]
+fn test_balance_weighted() {
+    let pairs = balance(&[8192, 8192], &[3.0, 1.0], 2.0).unwrap();
+    assert_eq!(pairs, vec![(1, 0, 4096)]);
+    let pairs = balance(&[8192, 8192], &[1.0, 0.0], 2.0).unwrap();
+    assert_eq!(pairs, vec![(1, 0, 8192)]);
+}
+
+#[test]
+fn test_balance_threshold() {
+    assert!(balance(&[8292, 8092], &[1.0, 1.0], 2.0).is_none());
+    assert_eq!(
+        balance(&[8292, 8092], &[1.0, 1.0], 0.0).unwrap(),
+        vec![(0, 1, 100)]
+    );
+}
+
+#[test]
+fn test_balance_bytes() {
+    let slots = vec![vec![(0, 100), (1, 50), (2, 50)], vec![(3, 0)]];
+    assert_eq!(
+        balance_bytes(&slots, &[1.0, 1.0], 2.0).unwrap(),
+        vec![(0, 1, 0)]
+    );
+    let slots = vec![vec![(0, 100)], vec![(1, 99)]];
+    assert!(balance_bytes(&slots, &[1.0, 1.0], 2.0).is_none());
+    let slots = vec![vec![(0, 300), (1, 10), (2, 10)], vec![(3, 10)]];
+    assert_eq!(
+        balance_bytes(&slots, &[1.0, 1.0], 2.0).unwrap(),
+        vec![(0, 1, 2), (0, 1, 1)]
+    );
+}
+
+#[test]
+fn test_balance_bytes_drain() {
+    let slots = vec![vec![(0, 10), (1, 0)], vec![(2, 10)], vec![(3, 20)]];
+    let moves = balance_bytes(&slots, &[0.0, 1.0, 1.0], 2.0).unwrap();
+    assert_eq!(moves.len(), 2);
+    assert!(moves.iter().all(|x| x.0 == 0 && x.1 != 0));
+    assert!(moves.contains(&(0, 1, 0)));
+}
+
+#[test]
+fn test_check_coverage() {
+    assert!(check_coverage(&[8192, 8192, 0]).is_ok());
+    assert!(check_coverage(&[100, 100]).is_err());
+    assert!(check_coverage(&[]).is_err());
+}
+
+#[derive(Debug, Clone)]
+pub enum Mode {
+    Slots,
+    Memory(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Rebalance {
+    pub weights: HashMap<String, f64>,
+    pub threshold: f64,
+    pub use_empty_masters: bool,
+    pub mode: Mode,
+}
+
+impl Rebalance {
+    pub fn plan(&self, nodes: &[Node]) -> AsResult<Vec<Move>> {
+        for key in self.weights.keys() {
+            if !nodes
+                .iter()
+                .any(|x| x.is_master() && (x.addr() == *key || x.name == *key))
+            {
+                return Err(Error::State(format!(
+                    "weighted node {} is not a master of the cluster",
+                    key
+                )));
+            }
+        }
+        let counts: Vec<usize> = nodes
+            .iter()
+            .filter(|x| x.is_master())
+            .map(|x| x.slots().len())
+            .collect();
+        check_coverage(&counts)?;
+        for master in nodes.iter().filter(|x| x.is_master()) {
+            let open = master.open_slots()?;
+            if !open.is_empty() {
+                return Err(Error::State(format!(
+                    "{} has open slots {:?}, run fix first",
+                    master.addr(),
+                    open
+                )));
+            }
+        }
+        let masters: Vec<&Node> = nodes
+            .iter()
+            .filter(|x| x.is_master() && (self.use_empty_masters || !x.slots().is_empty()))
+            .collect();
+        let weights: Vec<f64> = masters.iter().map(|x| self.weight(x)).collect();
+        if weights.iter().sum::<f64>() <= 0.0 {
+            return Err(Error::State("no master with positive weight".to_string()));
+        }
+        if let Mode::Memory(sample) = self.mode {
+            return self.plan_memory(&masters, &weights, sample);
+        }
+        let mut slots: Vec<Vec<usize>> = masters.iter().map(|x| x.slots()).collect();
+        let counts: Vec<usize> = slots.iter().map(|x| x.len()).collect();
+        let pairs = match balance(&counts, &weights, self.threshold) {
+            Some(pairs) => pairs,
+            None => {
+                println!(
+                    "no rebalancing needed, all masters are within {}% of their share",
+                    self.threshold
+                );
+                return Ok(vec![]);
+            }
+        };
+        let mut moves = Vec::new();
+        for (src, dst, count) in pairs {
+            println!(
+                "move {} slots from {} to {}",
+                count,
+                masters[src].addr(),
+                masters[dst].addr()
+            );
+            for _ in 0..count {
+                let slot = slots[src].pop().unwrap();
+                moves.push(Move::new(masters[src], masters[dst], slot));
+            }
+        }
+        Ok(moves)
+    }
+
+    fn plan_memory(
+        &self,
+        masters: &[&Node],
+        weights: &[f64],
+        sample: usize,
+    ) -> AsResult<Vec<Move>> {
+        let mut sizes = Vec::new();
+        for master in masters {
+            println!(
+                "estimate size of {} slots of {}",
+                master.slots().len(),
+                master.addr()
+            );
+            let mut slots = Vec::new();
+            for chunk in master.slots().chunks(slots::BATCH) {
+                let chunk_sizes = master.slot_sizes(chunk, sample)?;
+                slots.extend(chunk.iter().cloned().zip(chunk_sizes));
+            }
+            sizes.push(slots);
+        }
+        let plan = match balance_bytes(&sizes, weights, self.threshold) {
+            Some(plan) => plan,
+            None => {
+                println!(
+                    "no rebalancing needed, all masters are within {}% of their share of memory",
+                    self.threshold
+                );
+                return Ok(vec![]);
+            }
+        };
+        let mut moved: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+        let mut moves = Vec::new();
+        for (src, dst, slot) in plan {
+            *moved.entry((src, dst)).or_insert(0) += 1;
+            moves.push(Move::new(masters[src], masters[dst], slot));
+        }
+        for ((src, dst), count) in moved {
+            println!(
+                "move {} slots from {} to {}",
+                count,
+                masters[src].addr(),
+                masters[dst].addr()
+            );
+        }
+        Ok(moves)
+    }
+
+    fn weight(&self, node: &Node) -> f64 {
+        self.weights
+            .get(&node.addr())
+            .or_else(|| self.weights.get(&node.name))
+            .cloned()
+            .unwrap_or(1.0)
+    }
+}
+
+pub fn parse_weights<'a, I: Iterator<Item = &'a str>>(
+    weights: I,
+) -> Result<HashMap<String, f64>, String> {
+    let mut parsed = HashMap::new();
+    for weight in weights {
+        let idx = weight
+            .rfind('=')
+            .ok_or_else(|| format!("bad weight {}, must be formatted as node=weight", weight))?;
+        let value = weight[idx + 1..]
+            .parse::<f64>()
+            .ok()
+            .filter(|x| *x >= 0.0)
+            .ok_or_else(|| format!("bad weight {}, must be a non-negative number", weight))?;
+        parsed.insert(weight[..idx].to_string(), value);
+    }
+    Ok(parsed)
+}
+
+// the expected shares assume every slot is served, otherwise more slots would be
+// planned away from a master than it owns
+fn check_coverage(counts: &[usize]) -> AsResult<()> {
+    let covered: usize = counts.iter().sum();
+    if covered != CLUSTER_SLOTS {
+        return Err(Error::State(format!(
+            "masters serve {} of {} slots, run fix first",
+            covered, CLUSTER_SLOTS
+        )));
+    }
+    Ok(())
+}
+
+// mirrors redis-cli --cluster rebalance: returns (src, dst, count) index pairs,
+// or None when every master is within threshold percent of its expected share.
+fn balance(
+    counts: &[usize],
+    weights: &[f64],
+    threshold: f64,
+) -> Option<Vec<(usize, usize, usize)>> {
+    let total: f64 = weights.iter().sum();
+    let mut reached = false;
+    let mut balances: Vec<(usize, i64)> = Vec::new();
+    for (idx, (count, weight)) in counts.iter().zip(weights).enumerate() {
+        let expected = (CLUSTER_SLOTS as f64 / total * weight) as i64;
+        if *count > 0 {
+            let err = (100.0 - 100.0 * expected as f64 / *count as f64).abs();
+            reached |= err > threshold;
+        } else {
+            reached |= expected > 1;
+        }
+        balances.push((idx, *count as i64 - expected));
+    }
+    if !reached {
+        return None;
+    }
+
+    // expected shares are rounded down, hand the remainder to the receiving masters
+    let mut total_balance: i64 = balances.iter().map(|x| x.1).sum();
+    while total_balance > 0 {
+        for item in balances.iter_mut() {
+            if item.1 <= 0 && total_balance > 0 {
+                item.1 -= 1;
+                total_balance -= 1;
+            }
+        }
+    }
+    balances.sort_by_key(|x| x.1);
+
+    let mut pairs = Vec::new();
+    let (mut dst, mut src) = (0, balances.len() - 1);
+    while dst < src {
+        let count = balances[dst].1.abs().min(balances[src].1.abs());
+        if count > 0 {
+            pairs.push((balances[src].0, balances[dst].0, count as usize));
+        }
+        balances[dst].1 += count;
+        balances[src].1 -= count;
+        if balances[dst].1 == 0 {
+            dst += 1;
+        }
+        if balances[src].1 == 0 {
+            src -= 1;
+        }
+    }
+    Some(pairs)
+}
+
+// greedily moves the biggest slot that fits from the most loaded master to the
+// least loaded one, returns (src, dst, slot) or None when already balanced.
+fn balance_bytes(
+    slots: &[Vec<(usize, u64)>],
+    weights: &[f64],
+    threshold: f64,
+) -> Option<Vec<(usize, usize, usize)>> {
+    let total: u64 = slots.iter().flatten().map(|x| x.1).sum();
+    let weight: f64 = weights.iter().sum();
+    let targets: Vec<f64> = weights.iter().map(|w| total as f64 * w / weight).collect();
+    let mut loads: Vec<f64> = slots
+        .iter()
+        .map(|x| x.iter().map(|y| y.1).sum::<u64>() as f64)
+        .collect();
+    let excess = |loads: &[f64], idx: usize| loads[idx] - targets[idx];
+    let allowed = |idx: usize| targets[idx] * threshold / 100.0;
+    let reached = (0..slots.len()).any(|idx| {
+        if weights[idx] == 0.0 {
+            !slots[idx].is_empty()
+        } else {
+            excess(&loads, idx).abs() > allowed(idx)
+        }
+    });
+    if !reached {
+        return None;
+    }
+
+    let mut pools: Vec<Vec<(usize, u64)>> = slots.to_vec();
+    for pool in pools.iter_mut() {
+        pool.sort_by_key(|x| x.1);
+    }
+    let receivers: Vec<usize> = (0..slots.len()).filter(|x| weights[*x] > 0.0).collect();
+    let least_loaded = |loads: &[f64]| -> usize {
+        *receivers
+            .iter()
+            .min_by(|a, b| excess(loads, **a).total_cmp(&excess(loads, **b)))
+            .unwrap()
+    };
+
+    let mut moves = Vec::new();
+    for src in (0..slots.len()).filter(|x| weights[*x] == 0.0) {
+        while let Some((slot, size)) = pools[src].pop() {
+            let dst = least_loaded(&loads);
+            loads[src] -= size as f64;
+            loads[dst] += size as f64;
+            moves.push((src, dst, slot));
+        }
+    }
+    loop {
+        let src = *receivers
+            .iter()
+            .max_by(|a, b| excess(&loads, **a).total_cmp(&excess(&loads, **b)))
+            .unwrap();
+        let dst = least_loaded(&loads);
+        let room = excess(&loads, src).min(-excess(&loads, dst));
+        if excess(&loads, src) <= allowed(src) || room <= 0.0 {
+            break;
+        }
+        let idx = match pools[src]
+            .iter()
+            .rposition(|x| x.1 > 0 && x.1 as f64 <= room)
+        {
+            Some(idx) => idx,
+            None => break,
+        };
+        let (slot, size) = pools[src].remove(idx);
+        loads[src] -= size as f64;
+        loads[dst] += size as f64;
+        pools[dst].push((slot, size));
+        pools[dst].sort_by_key(|x| x.1);
+        moves.push((src, dst, slot));
+    }
+    Some(moves)
+}
@@ -0,0 +1,170 @@
+use cluster::{Error, Node};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use util;
+
+#[test]
+fn test_parse_columns() {
+    let columns = parse_columns("addr, role,ops").unwrap();
+    assert_eq!(columns, vec!["addr", "role", "ops"]);
+    assert!(parse_columns("addr,cpu").is_err());
+    assert!(parse_columns("").is_err());
+}
+
+#[test]
+fn test_render_csv() {
+    let report = Report {
+        columns: vec!["addr".to_string(), "version".to_string()],
+        rows: vec![
+            vec!["127.0.0.1:7000".to_string(), "5.0.3".to_string()],
+            vec!["127.0.0.1:7001".to_string(), "a,\"b\"".to_string()],
+        ],
+    };
+    let mut out = Vec::new();
+    report.write_to(&mut out, &Format::Csv).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "addr,version\n127.0.0.1:7000,5.0.3\n127.0.0.1:7001,\"a,\"\"b\"\"\"\n"
+    );
+}
+
+#[test]
+fn test_render_json() {
+    let report = Report {
+        columns: vec!["addr".to_string(), "role".to_string()],
+        rows: vec![vec!["127.0.0.1:7000".to_string(), "master".to_string()]],
+    };
+    let mut out = Vec::new();
+    report.write_to(&mut out, &Format::Json).unwrap();
+    let value: Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(value[0]["addr"], "127.0.0.1:7000");
+    assert_eq!(value[0]["role"], "master");
+}
+
+pub const COLUMNS: &[&str] = &[
+    "addr", "pid", "role", "used", "max", "clients", "ops", "cmds", "conns", "version",
+];
+
+#[derive(Debug)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Option<Format> {
+        match format {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Report {
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Report {
+    pub fn new(addr: &str, columns: Vec<String>) -> Result<Report, Error> {
+        let seed = Node::new(addr.as_bytes())?;
+        let mut rows = Vec::new();
+        for node in seed.nodes()? {
+            if node.address().host.is_empty() {
+                continue;
+            }
+            let info = node.redis_info().unwrap_or_else(|err| {
+                eprintln!("collect info of {} fail: {}", node.addr(), err);
+                HashMap::new()
+            });
+            rows.push(
+                columns
+                    .iter()
+                    .map(|column| field(&node, &info, column))
+                    .collect(),
+            );
+        }
+        Ok(Report { columns, rows })
+    }
+
+    pub fn write(&self, output: &str, format: &Format) -> io::Result<()> {
+        util::write_output(output, |out| self.write_to(out, format))
+    }
+
+    fn write_to<W: Write>(&self, out: &mut W, format: &Format) -> io::Result<()> {
+        match format {
+            Format::Csv => {
+                writeln!(out, "{}", self.columns.join(","))?;
+                for row in &self.rows {
+                    let line: Vec<String> = row.iter().map(|x| csv_escape(x)).collect();
+                    writeln!(out, "{}", line.join(","))?;
+                }
+            }
+            Format::Json => {
+                let rows: Vec<Value> = self
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        let mut obj = Map::new();
+                        for (column, value) in self.columns.iter().zip(row.iter()) {
+                            obj.insert(column.clone(), Value::String(value.clone()));
+                        }
+                        Value::Object(obj)
+                    })
+                    .collect();
+                serde_json::to_writer_pretty(&mut *out, &rows)?;
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_columns(columns: &str) -> Result<Vec<String>, String> {
+    let columns: Vec<String> = columns
+        .split(',')
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .collect();
+    if columns.is_empty() {
+        return Err("no column given".to_string());
+    }
+    for column in &columns {
+        if !COLUMNS.contains(&column.as_str()) {
+            return Err(format!(
+                "unknown column {}, must be one of {}",
+                column,
+                COLUMNS.join(",")
+            ));
+        }
+    }
+    Ok(columns)
+}
+
+fn field(node: &Node, info: &HashMap<String, String>, column: &str) -> String {
+    let key = match column {
+        "addr" => return node.addr(),
+        "pid" => "process_id",
+        "role" => "role",
+        "used" => "used_memory",
+        "max" => "maxmemory",
+        "clients" => "connected_clients",
+        "ops" => "instantaneous_ops_per_sec",
+        "cmds" => "total_commands_processed",
+        "conns" => "total_connections_received",
+        "version" => "redis_version",
+        _ => return String::new(),
+    };
+    info.get(key).cloned().unwrap_or_default()
+}
+
+pub fn csv_escape(value: &str) -> String {
+    if value.contains(',') || value.contains('"') || value.contains('\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
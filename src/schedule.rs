@@ -0,0 +1,230 @@
+use addr::Addr;
+use cluster::{self, AsResult, Error, Node};
+use journal::{self, Journal};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+#[cfg(test)]
+fn peer(addr: &str) -> Peer {
+    Peer {
+        addr: Addr::parse(addr).unwrap(),
+        name: addr.to_string(),
+    }
+}
+
+#[cfg(test)]
+fn slot_move(src: &str, dst: &str, slot: usize) -> Move {
+    Move {
+        src: peer(src),
+        dst: peer(dst),
+        slot,
+    }
+}
+
+#[test]
+fn test_next_respects_node_limit() {
+    let mut state = State::new(vec![
+        slot_move("127.0.0.1:7000", "127.0.0.1:7001", 1),
+        slot_move("127.0.0.1:7000", "127.0.0.1:7002", 2),
+        slot_move("127.0.0.1:7003", "127.0.0.1:7004", 3),
+        slot_move("127.0.0.1:7003", "127.0.0.1:7001", 4),
+    ]);
+    let first = state.next(1).unwrap();
+    assert_eq!(first.slot, 1);
+    assert_eq!(state.next(1).unwrap().slot, 3);
+    assert!(state.next(1).is_none());
+    assert_eq!(state.running, 2);
+
+    state.done(&first);
+    assert_eq!(state.next(1).unwrap().slot, 2);
+    assert!(state.next(1).is_none());
+    assert_eq!(state.pending.len(), 1);
+}
+
+#[test]
+fn test_next_keeps_order_with_higher_limit() {
+    let mut state = State::new(vec![
+        slot_move("127.0.0.1:7000", "127.0.0.1:7001", 1),
+        slot_move("127.0.0.1:7000", "127.0.0.1:7001", 2),
+        slot_move("127.0.0.1:7000", "127.0.0.1:7001", 3),
+    ]);
+    assert_eq!(state.next(2).unwrap().slot, 1);
+    assert_eq!(state.next(2).unwrap().slot, 2);
+    assert!(state.next(2).is_none());
+}
+
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub addr: Addr,
+    pub name: String,
+}
+
+impl Peer {
+    fn from_node(node: &Node) -> Peer {
+        Peer {
+            addr: node.address(),
+            name: node.name.clone(),
+        }
+    }
+
+    fn node(&self) -> Node {
+        let mut node = Node::from_addr(self.addr.clone());
+        node.name = self.name.clone();
+        node
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Move {
+    pub src: Peer,
+    pub dst: Peer,
+    pub slot: usize,
+}
+
+impl Move {
+    pub fn new(src: &Node, dst: &Node, slot: usize) -> Move {
+        Move {
+            src: Peer::from_node(src),
+            dst: Peer::from_node(dst),
+            slot,
+        }
+    }
+}
+
+struct State {
+    pending: VecDeque<Move>,
+    busy: HashMap<String, usize>,
+    running: usize,
+    failed: usize,
+    journal: Option<Journal>,
+}
+
+impl State {
+    fn new(moves: Vec<Move>) -> State {
+        State {
+            pending: moves.into_iter().collect(),
+            busy: HashMap::new(),
+            running: 0,
+            failed: 0,
+            journal: None,
+        }
+    }
+
+    fn load(&self, name: &str) -> usize {
+        self.busy.get(name).cloned().unwrap_or(0)
+    }
+
+    fn next(&mut self, per_node: usize) -> Option<Move> {
+        let idx = self
+            .pending
+            .iter()
+            .position(|x| self.load(&x.src.name) < per_node && self.load(&x.dst.name) < per_node)?;
+        let next = self.pending.remove(idx)?;
+        *self.busy.entry(next.src.name.clone()).or_insert(0) += 1;
+        *self.busy.entry(next.dst.name.clone()).or_insert(0) += 1;
+        self.running += 1;
+        Some(next)
+    }
+
+    fn done(&mut self, done: &Move) {
+        for name in &[&done.src.name, &done.dst.name] {
+            if let Some(busy) = self.busy.get_mut(*name) {
+                *busy -= 1;
+            }
+        }
+        self.running -= 1;
+    }
+}
+
+pub fn run(moves: Vec<Move>) -> AsResult<()> {
+    if let Some(x) = moves.iter().find(|x| x.src.name == x.dst.name) {
+        return Err(Error::State(format!(
+            "slot {} can not be migrated from {} to itself",
+            x.slot, x.src.addr
+        )));
+    }
+    let journal = if cluster::is_dry_run() || moves.is_empty() {
+        None
+    } else {
+        journal::start(&moves)?
+    };
+    run_with(moves, journal)
+}
+
+pub fn run_with(moves: Vec<Move>, journal: Option<Journal>) -> AsResult<()> {
+    let opts = cluster::migration();
+    let per_node = opts.per_node;
+    let workers = opts.parallel.min(moves.len());
+    let total = moves.len();
+    let mut state = State::new(moves);
+    state.journal = journal;
+    let shared = Arc::new((Mutex::new(state), Condvar::new()));
+    let handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || work(&shared, per_node))
+        })
+        .collect();
+    for handle in handles {
+        handle.join().expect("migrate worker panicked");
+    }
+    let state = shared.0.lock().unwrap();
+    if state.failed > 0 {
+        let hint = match state.journal {
+            Some(ref journal) => format!(", resume with `rckit resume {}`", journal.path()),
+            None => String::new(),
+        };
+        return Err(Error::State(format!(
+            "{} of {} slot migrations failed, {} not started{}",
+            state.failed,
+            total,
+            state.pending.len(),
+            hint
+        )));
+    }
+    Ok(())
+}
+
+fn work(shared: &(Mutex<State>, Condvar), per_node: usize) {
+    let (lock, cvar) = shared;
+    let mut nodes: HashMap<String, Node> = HashMap::new();
+    loop {
+        let next = {
+            let mut state = lock.lock().unwrap();
+            loop {
+                if state.failed > 0 || state.pending.is_empty() {
+                    return;
+                }
+                if let Some(next) = state.next(per_node) {
+                    break next;
+                }
+                state = cvar.wait(state).unwrap();
+            }
+        };
+        let src = nodes
+            .entry(next.src.name.clone())
+            .or_insert_with(|| next.src.node())
+            .clone();
+        let dst = nodes
+            .entry(next.dst.name.clone())
+            .or_insert_with(|| next.dst.node())
+            .clone();
+        let result = cluster::migrate_slot(&src, &dst, next.slot);
+
+        let mut state = lock.lock().unwrap();
+        state.done(&next);
+        if let Err(err) = result {
+            eprintln!(
+                "migrate slot {} from {} to {} fail: {}",
+                next.slot, next.src.addr, next.dst.addr, err
+            );
+            state.failed += 1;
+        } else if let Some(ref mut journal) = state.journal {
+            if let Err(err) = journal.done(next.slot) {
+                eprintln!("write journal {} fail: {}", journal.path(), err);
+            }
+        }
+        cvar.notify_all();
+    }
+}
@@ -0,0 +1,193 @@
+use cluster::{AsResult, Error, Node};
+use report::Format as Table;
+use std::cmp::Reverse;
+use std::io::{self, Write};
+use util;
+
+#[test]
+fn test_cells() {
+    let counts = vec![1, 2, 3, 4, 5, 6, 7];
+    assert_eq!(cells(&counts, 3), vec![6, 15, 7]);
+    assert_eq!(cells(&counts, 10), vec![28]);
+}
+
+#[test]
+fn test_shade() {
+    assert_eq!(shade(&[0, 1, 25, 26, 50, 75, 100], 100), " ░░▒▒▓█");
+    assert_eq!(shade(&[0, 0], 0), "  ");
+}
+
+// how many slots of a pipeline are sent at once
+pub const BATCH: usize = 1024;
+const SHADES: &[char] = &[' ', '░', '▒', '▓', '█'];
+
+#[derive(Debug)]
+pub enum Format {
+    Heatmap,
+    Table(Table),
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Option<Format> {
+        match format {
+            "heatmap" => Some(Format::Heatmap),
+            _ => Table::parse(format).map(Format::Table),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SlotKeys {
+    pub slot: usize,
+    pub keys: usize,
+    pub node: String,
+}
+
+struct Master {
+    addr: String,
+    ranges: Vec<(usize, usize)>,
+    counts: Vec<usize>,
+}
+
+pub struct Slots {
+    masters: Vec<Master>,
+}
+
+impl Slots {
+    pub fn new(addr: &str) -> AsResult<Slots> {
+        let seed = Node::new(addr.as_bytes())?;
+        let mut masters = Vec::new();
+        for node in seed.nodes()? {
+            let mut slots = node.slots();
+            slots.sort();
+            if !node.is_master() || slots.is_empty() {
+                continue;
+            }
+            let mut counts = Vec::with_capacity(slots.len());
+            for chunk in slots.chunks(BATCH) {
+                counts.extend(node.countkeysinslots(chunk)?);
+            }
+            let ranges = util::slot_ranges(&slots)
+                .split(',')
+                .filter_map(util::parse_slot_range)
+                .collect();
+            masters.push(Master {
+                addr: node.addr(),
+                ranges,
+                counts,
+            });
+        }
+        if masters.is_empty() {
+            return Err(Error::State("no master serves any slot".to_string()));
+        }
+        masters.sort_by_key(|x| x.ranges[0].0);
+        Ok(Slots { masters })
+    }
+
+    fn rows(&self) -> Vec<SlotKeys> {
+        let mut rows: Vec<SlotKeys> = self
+            .masters
+            .iter()
+            .flat_map(|master| {
+                master
+                    .ranges
+                    .iter()
+                    .flat_map(|&(start, end)| start..=end)
+                    .zip(&master.counts)
+                    .map(move |(slot, &keys)| SlotKeys {
+                        slot,
+                        keys,
+                        node: master.addr.clone(),
+                    })
+            })
+            .collect();
+        rows.sort_by_key(|x| x.slot);
+        rows
+    }
+
+    pub fn write(&self, output: &str, format: &Format, cell: usize) -> io::Result<()> {
+        util::write_output(output, |out| self.write_to(out, format, cell))
+    }
+
+    fn write_to<W: Write>(&self, out: &mut W, format: &Format, cell: usize) -> io::Result<()> {
+        match format {
+            Format::Table(Table::Csv) => {
+                writeln!(out, "slot,keys,node")?;
+                for row in self.rows() {
+                    writeln!(out, "{},{},{}", row.slot, row.keys, row.node)?;
+                }
+            }
+            Format::Table(Table::Json) => {
+                serde_json::to_writer_pretty(&mut *out, &self.rows())?;
+                writeln!(out)?;
+            }
+            Format::Heatmap => self.heatmap(out, cell)?,
+        }
+        Ok(())
+    }
+
+    // one line per slot range of every master, each character covering cell slots
+    fn heatmap<W: Write>(&self, out: &mut W, cell: usize) -> io::Result<()> {
+        let mut lines = Vec::new();
+        for master in &self.masters {
+            let mut offset = 0;
+            let mut ranges = Vec::new();
+            for &(start, end) in &master.ranges {
+                let counts = &master.counts[offset..offset + end - start + 1];
+                offset += counts.len();
+                ranges.push((start, end, counts, cells(counts, cell)));
+            }
+            lines.push((master, ranges));
+        }
+        let max = lines
+            .iter()
+            .flat_map(|(_, ranges)| ranges.iter().flat_map(|x| x.3.iter()))
+            .cloned()
+            .max()
+            .unwrap_or(0);
+        writeln!(
+            out,
+            "every character is {} slots, █ is the busiest with {} keys",
+            cell, max
+        )?;
+        for (master, ranges) in lines {
+            let keys: usize = master.counts.iter().sum();
+            let slots = master.counts.len();
+            writeln!(out, "{} {} slots {} keys", master.addr, slots, keys)?;
+            for (start, end, counts, cells) in ranges {
+                let (busiest, most) = counts
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|&(idx, keys)| (keys, Reverse(idx)))
+                    .map(|(idx, keys)| (start + idx, *keys))
+                    .unwrap_or((start, 0));
+                writeln!(
+                    out,
+                    "  {:>11} |{}| busiest slot {} with {} keys",
+                    format!("{}-{}", start, end),
+                    shade(&cells, max),
+                    busiest,
+                    most
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn cells(counts: &[usize], cell: usize) -> Vec<usize> {
+    counts.chunks(cell).map(|x| x.iter().sum()).collect()
+}
+
+fn shade(cells: &[usize], max: usize) -> String {
+    cells
+        .iter()
+        .map(|&keys| {
+            if keys == 0 || max == 0 {
+                return SHADES[0];
+            }
+            let level = (keys * (SHADES.len() - 1)).div_ceil(max);
+            SHADES[level]
+        })
+        .collect()
+}
@@ -0,0 +1,311 @@
+use cluster::AsResult;
+use exporter::{self, Sample};
+use parser::{Flag, LinkState};
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(test)]
+fn info(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_rows() {
+    let entries = ::parser::parse_nodes(
+        "\
+cccc 127.0.0.1:7002@17002 slave aaaa 0 0 1 connected
+bbbb 127.0.0.1:7001@17001 master,fail? - 0 0 2 connected 5461-16383
+aaaa 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-5460 [5461->-bbbb]
+",
+    )
+    .unwrap();
+    let infos = vec![
+        Some(info(&[
+            ("master_link_status", "up"),
+            ("slave_repl_offset", "90"),
+        ])),
+        None,
+        Some(info(&[
+            ("used_memory_human", "1.00M"),
+            ("instantaneous_ops_per_sec", "12"),
+            ("connected_clients", "3"),
+            ("master_repl_offset", "100"),
+        ])),
+    ];
+    let nodes: Vec<Sample> = entries
+        .into_iter()
+        .zip(infos)
+        .map(|(entry, info)| Sample { entry, info })
+        .collect();
+    let rows = rows(&nodes);
+    let addrs: Vec<&str> = rows.iter().map(|x| x.cells[0].as_str()).collect();
+    assert_eq!(
+        addrs,
+        vec!["127.0.0.1:7000", "  127.0.0.1:7002", "127.0.0.1:7001"]
+    );
+    assert_eq!(
+        rows[0].cells[1..],
+        [
+            "master",
+            "5461",
+            "1.00M",
+            "12",
+            "3",
+            "connected",
+            "migrating 5461"
+        ]
+    );
+    assert_eq!(rows[0].level, Level::Warn);
+    assert_eq!(rows[1].cells[6], "up, lag 10");
+    assert_eq!(rows[1].level, Level::Normal);
+    assert_eq!(rows[2].cells[3], "down");
+    assert_eq!(rows[2].cells[7], "pfail");
+    assert_eq!(rows[2].level, Level::Fail);
+}
+
+const HEADER: &[&str] = &[
+    "ADDR", "ROLE", "SLOTS", "MEMORY", "OPS/S", "CLIENTS", "LINK", "NOTES",
+];
+
+#[derive(Debug, PartialEq)]
+enum Level {
+    Normal,
+    Warn,
+    Fail,
+}
+
+struct Row {
+    cells: Vec<String>,
+    level: Level,
+}
+
+pub fn run(seed: &str, interval: Duration) -> AsResult<()> {
+    let terminal = io::stdout().is_terminal();
+    let mut seeds = vec![seed.to_string()];
+    loop {
+        let snapshot = exporter::snapshot(&mut seeds);
+        let screen = render(seed, snapshot.as_ref(), terminal);
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+        if terminal {
+            // move home and clear the screen before drawing the next frame
+            write!(handle, "\x1b[H\x1b[2J")?;
+        }
+        write!(handle, "{}", screen)?;
+        handle.flush()?;
+        thread::sleep(interval);
+    }
+}
+
+fn render(
+    seed: &str,
+    snapshot: Option<&(HashMap<String, String>, Vec<Sample>)>,
+    color: bool,
+) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0);
+    let clock = format!(
+        "{:02}:{:02}:{:02} UTC",
+        now / 3600 % 24,
+        now / 60 % 60,
+        now % 60
+    );
+    let (cluster, nodes) = match snapshot {
+        Some((cluster, nodes)) => (cluster, nodes),
+        None => {
+            return format!(
+                "rckit top {}  {}\n{}\n",
+                seed,
+                clock,
+                paint("no seed answered CLUSTER INFO", &Level::Fail, color)
+            )
+        }
+    };
+    let get = |key: &str| cluster.get(key).map(|x| x.as_str()).unwrap_or("?");
+    let state = get("cluster_state");
+    let level = if state == "ok" {
+        Level::Normal
+    } else {
+        Level::Fail
+    };
+    let mut out = format!(
+        "rckit top {}  {}  cluster_state {}  slots {} assigned {} ok {} pfail {} fail  nodes {}\n\n",
+        seed,
+        clock,
+        paint(state, &level, color),
+        get("cluster_slots_assigned"),
+        get("cluster_slots_ok"),
+        get("cluster_slots_pfail"),
+        get("cluster_slots_fail"),
+        get("cluster_known_nodes"),
+    );
+    let rows = rows(nodes);
+    let widths: Vec<usize> = (0..HEADER.len())
+        .map(|idx| {
+            rows.iter()
+                .map(|x| x.cells[idx].chars().count())
+                .chain(Some(HEADER[idx].len()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let line = |cells: &[String]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+    let header: Vec<String> = HEADER.iter().map(|x| x.to_string()).collect();
+    out.push_str(&line(&header));
+    out.push('\n');
+    for row in &rows {
+        out.push_str(&paint(&line(&row.cells), &row.level, color));
+        out.push('\n');
+    }
+    out
+}
+
+fn paint(text: &str, level: &Level, color: bool) -> String {
+    match level {
+        _ if !color => text.to_string(),
+        Level::Normal => text.to_string(),
+        Level::Warn => format!("\x1b[33m{}\x1b[0m", text),
+        Level::Fail => format!("\x1b[1;31m{}\x1b[0m", text),
+    }
+}
+
+// masters by their first slot, each followed by its replicas, then the nodes of unknown masters
+fn rows(nodes: &[Sample]) -> Vec<Row> {
+    let mut masters: Vec<&Sample> = nodes
+        .iter()
+        .filter(|x| x.entry.has(&Flag::Master))
+        .collect();
+    masters.sort_by_key(|x| {
+        let first = x.entry.slots.first().map(|x| x.0).unwrap_or(usize::MAX);
+        (first, x.entry.addr.to_string())
+    });
+    let offsets: HashMap<&str, u64> = nodes
+        .iter()
+        .filter_map(|x| {
+            let offset = x.info.as_ref()?.get("master_repl_offset")?;
+            Some((x.entry.id.as_str(), offset.trim().parse().ok()?))
+        })
+        .collect();
+    let mut rows = Vec::new();
+    let mut shown = 0;
+    for master in &masters {
+        rows.push(row(master, "", &offsets));
+        shown += 1;
+        for replica in nodes
+            .iter()
+            .filter(|x| x.entry.master_id.as_ref() == Some(&master.entry.id))
+        {
+            rows.push(row(replica, "  ", &offsets));
+            shown += 1;
+        }
+    }
+    if shown < nodes.len() {
+        for node in nodes.iter().filter(|x| {
+            !x.entry.has(&Flag::Master)
+                && !masters
+                    .iter()
+                    .any(|m| x.entry.master_id.as_ref() == Some(&m.entry.id))
+        }) {
+            rows.push(row(node, "", &offsets));
+        }
+    }
+    rows
+}
+
+fn row(node: &Sample, indent: &str, offsets: &HashMap<&str, u64>) -> Row {
+    let entry = &node.entry;
+    let master = entry.has(&Flag::Master);
+    let field = |key: &str| {
+        node.info
+            .as_ref()
+            .and_then(|x| x.get(key))
+            .map(|x| x.trim().to_string())
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let link = if master {
+        match entry.link_state {
+            LinkState::Connected => "connected".to_string(),
+            LinkState::Disconnected => "disconnected".to_string(),
+        }
+    } else {
+        let status = field("master_link_status");
+        let lag = entry
+            .master_id
+            .as_ref()
+            .and_then(|x| offsets.get(x.as_str()))
+            .and_then(|master| {
+                let offset: u64 = node.info.as_ref()?.get("slave_repl_offset")?.parse().ok()?;
+                Some(master.saturating_sub(offset))
+            });
+        match lag {
+            Some(lag) => format!("{}, lag {}", status, lag),
+            None => status,
+        }
+    };
+    let mut notes = Vec::new();
+    let mut level = Level::Normal;
+    if entry.has(&Flag::Fail) {
+        notes.push("fail".to_string());
+        level = Level::Fail;
+    } else if entry.has(&Flag::PFail) {
+        notes.push("pfail".to_string());
+        level = Level::Fail;
+    }
+    if node.info.is_none() {
+        level = Level::Fail;
+    }
+    let mut open = |state: &str, slots: &HashMap<usize, String>| {
+        if slots.is_empty() {
+            return;
+        }
+        let mut slots: Vec<usize> = slots.keys().cloned().collect();
+        slots.sort();
+        let slots: Vec<String> = slots.iter().map(|x| x.to_string()).collect();
+        notes.push(format!("{} {}", state, slots.join(",")));
+    };
+    open("migrating", &entry.migrating);
+    open("importing", &entry.importing);
+    if level == Level::Normal
+        && (!entry.migrating.is_empty()
+            || !entry.importing.is_empty()
+            || (!master && field("master_link_status") != "up"))
+    {
+        level = Level::Warn;
+    }
+    let memory = if node.info.is_some() {
+        field("used_memory_human")
+    } else {
+        "down".to_string()
+    };
+    Row {
+        cells: vec![
+            format!("{}{}", indent, entry.addr),
+            if master { "master" } else { "replica" }.to_string(),
+            if master {
+                entry.slot_count().to_string()
+            } else {
+                "-".to_string()
+            },
+            memory,
+            field("instantaneous_ops_per_sec"),
+            field("connected_clients"),
+            link,
+            notes.join(" "),
+        ],
+        level,
+    }
+}
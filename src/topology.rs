@@ -0,0 +1,199 @@
+use cluster::{self, AsResult, Node};
+use parser::{Flag, NodeEntry};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use util;
+
+#[cfg(test)]
+fn sample() -> Topology {
+    let entries = ::parser::parse_nodes(
+        "\
+bbbb 127.0.0.1:7001@17001,redis-1 master - 0 0 2 connected 5461-10922 [5461->-aaaa]
+aaaa 127.0.0.1:7000@17000,redis-0 myself,master - 0 0 1 connected 0-5460 10923 [5461-<-bbbb]
+cccc 127.0.0.1:7002@17002 slave aaaa 0 0 1 connected
+",
+    )
+    .unwrap();
+    Topology::from_entries("127.0.0.1:7000", 1546300800, &entries)
+}
+
+#[test]
+fn test_topology_from_entries() {
+    let topology = sample();
+    assert_eq!(topology.version, VERSION);
+    let addrs: Vec<&str> = topology.nodes.iter().map(|x| x.addr.as_str()).collect();
+    assert_eq!(
+        addrs,
+        vec!["127.0.0.1:7000", "127.0.0.1:7001", "127.0.0.1:7002"]
+    );
+    let first = &topology.nodes[0];
+    assert_eq!(first.role, "master");
+    assert_eq!(first.hostname, Some("redis-0".to_string()));
+    assert_eq!(first.slots, vec!["0-5460", "10923"]);
+    assert_eq!(first.importing.get(&5461), Some(&"bbbb".to_string()));
+    assert_eq!(first.flags, vec!["master", "myself"]);
+    let last = &topology.nodes[2];
+    assert_eq!(last.role, "slave");
+    assert_eq!(last.replica_of, Some("aaaa".to_string()));
+}
+
+#[test]
+fn test_topology_roundtrip() {
+    let topology = sample();
+    for format in &[Format::Json, Format::Yaml] {
+        let mut out = Vec::new();
+        topology.write_to(&mut out, format).unwrap();
+        let back = Topology::parse(&String::from_utf8(out).unwrap(), format).unwrap();
+        assert_eq!(back, topology);
+    }
+}
+
+pub const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum Format {
+    Json,
+    Yaml,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Option<Format> {
+        match format {
+            "json" => Some(Format::Json),
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Topology {
+    pub version: u32,
+    pub created: u64,
+    pub seed: String,
+    pub nodes: Vec<TopologyNode>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TopologyNode {
+    pub id: String,
+    pub addr: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zone: Option<String>,
+    pub role: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replica_of: Option<String>,
+    #[serde(default)]
+    pub slots: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub migrating: BTreeMap<usize, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub importing: BTreeMap<usize, String>,
+    #[serde(default)]
+    pub flags: Vec<String>,
+    #[serde(default)]
+    pub config_epoch: u64,
+}
+
+impl Topology {
+    pub fn new(addr: &str) -> AsResult<Topology> {
+        let seed = Node::new(addr.as_bytes())?;
+        let mut entries = seed.entries()?;
+        cluster::fill_open_slots(&mut entries);
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or(0);
+        Ok(Topology::from_entries(&seed.addr(), created, &entries))
+    }
+
+    pub fn from_entries(seed: &str, created: u64, entries: &[NodeEntry]) -> Topology {
+        let mut nodes: Vec<TopologyNode> = entries
+            .iter()
+            .map(|entry| {
+                let mut flags: Vec<String> = entry.flags.iter().map(|x| x.to_string()).collect();
+                flags.sort();
+                TopologyNode {
+                    id: entry.id.clone(),
+                    addr: entry.addr.to_string(),
+                    hostname: entry.addr.hostname.clone(),
+                    zone: ::zone::lookup(&entry.addr),
+                    role: if entry.has(&Flag::Master) {
+                        "master".to_string()
+                    } else {
+                        "slave".to_string()
+                    },
+                    replica_of: entry.master_id.clone(),
+                    slots: entry
+                        .slots
+                        .iter()
+                        .map(|&(start, end)| {
+                            if start == end {
+                                start.to_string()
+                            } else {
+                                format!("{}-{}", start, end)
+                            }
+                        })
+                        .collect(),
+                    migrating: entry.migrating.clone().into_iter().collect(),
+                    importing: entry.importing.clone().into_iter().collect(),
+                    flags,
+                    config_epoch: entry.config_epoch,
+                }
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.addr.cmp(&b.addr));
+        Topology {
+            version: VERSION,
+            created,
+            seed: seed.to_string(),
+            nodes,
+        }
+    }
+
+    pub fn load(path: &str) -> AsResult<Topology> {
+        let content = fs::read_to_string(path)?;
+        let format = if path.ends_with(".json") {
+            Format::Json
+        } else {
+            Format::Yaml
+        };
+        Topology::parse(&content, &format)
+    }
+
+    pub fn parse(content: &str, format: &Format) -> AsResult<Topology> {
+        let topology: Topology = match format {
+            Format::Json => serde_json::from_str(content).map_err(|err| err.to_string()),
+            Format::Yaml => serde_yaml::from_str(content).map_err(|err| err.to_string()),
+        }
+        .map_err(|err| ::cluster::Error::State(format!("bad topology: {}", err)))?;
+        if topology.version != VERSION {
+            return Err(::cluster::Error::State(format!(
+                "unsupported topology version {}, expect {}",
+                topology.version, VERSION
+            )));
+        }
+        Ok(topology)
+    }
+
+    pub fn write(&self, output: &str, format: &Format) -> io::Result<()> {
+        util::write_output(output, |out| self.write_to(out, format))
+    }
+
+    fn write_to<W: Write>(&self, out: &mut W, format: &Format) -> io::Result<()> {
+        match format {
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+            }
+            Format::Yaml => {
+                serde_yaml::to_writer(&mut *out, self).map_err(io::Error::other)?;
+            }
+        }
+        Ok(())
+    }
+}
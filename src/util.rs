@@ -1,3 +1,35 @@
+use std::fs::File;
+use std::io::{self, Write};
+
+#[test]
+fn test_slot_ranges() {
+    assert_eq!(slot_ranges(&[]), "");
+    assert_eq!(slot_ranges(&[1]), "1");
+    assert_eq!(slot_ranges(&[0, 1, 2, 5, 7, 8]), "0-2,5,7-8");
+}
+
+#[test]
+fn test_parse_slot_range() {
+    assert_eq!(parse_slot_range("0-5460"), Some((0, 5460)));
+    assert_eq!(parse_slot_range(" 7 "), Some((7, 7)));
+    assert_eq!(parse_slot_range("10-2"), None);
+    assert_eq!(parse_slot_range("0-16384"), None);
+    assert_eq!(parse_slot_range("a"), None);
+}
+
+#[test]
+fn test_key_slot() {
+    assert_eq!(crc16(b"123456789"), 0x31c3);
+    assert_eq!(key_slot(b"foo"), 12182);
+    assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"user1000"));
+    assert_eq!(
+        key_slot(b"foo{}{bar}"),
+        crc16(b"foo{}{bar}") as usize % 16384
+    );
+    assert_eq!(key_slot(b"foo{bar}{zap}"), key_slot(b"bar"));
+}
+
+pub const CLUSTER_SLOTS: usize = 16384;
 
 pub fn divide(n: usize, m: usize) -> Vec<usize> {
     let avg = n / m;
@@ -14,3 +46,76 @@ pub fn divide(n: usize, m: usize) -> Vec<usize> {
     }
     c
 }
+
+pub fn slot_ranges(slots: &[usize]) -> String {
+    let mut ranges: Vec<String> = Vec::new();
+    let mut idx = 0;
+    while idx < slots.len() {
+        let start = slots[idx];
+        let mut end = start;
+        while idx + 1 < slots.len() && slots[idx + 1] == end + 1 {
+            idx += 1;
+            end = slots[idx];
+        }
+        if start == end {
+            ranges.push(start.to_string());
+        } else {
+            ranges.push(format!("{}-{}", start, end));
+        }
+        idx += 1;
+    }
+    ranges.join(",")
+}
+
+pub fn parse_slot_range(range: &str) -> Option<(usize, usize)> {
+    let mut scope = range.splitn(2, '-');
+    let start: usize = scope.next()?.trim().parse().ok()?;
+    let end: usize = match scope.next() {
+        Some(end) => end.trim().parse().ok()?,
+        None => start,
+    };
+    if start > end || end >= CLUSTER_SLOTS {
+        return None;
+    }
+    Some((start, end))
+}
+
+pub fn key_slot(key: &[u8]) -> usize {
+    let key = match key.iter().position(|x| *x == b'{') {
+        Some(open) => match key[open + 1..].iter().position(|x| *x == b'}') {
+            Some(len) if len > 0 => &key[open + 1..open + 1 + len],
+            _ => key,
+        },
+        None => key,
+    };
+    crc16(key) as usize % CLUSTER_SLOTS
+}
+
+// crc16 xmodem, as used by redis cluster
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in data {
+        crc ^= u16::from(*byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+// hands write the standard output when output is "stdout", a new file of that name otherwise
+pub fn write_output<F>(output: &str, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut Box<dyn Write>) -> io::Result<()>,
+{
+    let mut out: Box<dyn Write> = if output == "stdout" {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(output)?)
+    };
+    write(&mut out)
+}
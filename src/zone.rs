@@ -0,0 +1,97 @@
+use addr::Addr;
+use cluster::{AsResult, Error};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::OnceLock;
+
+#[test]
+fn test_split_labels() {
+    assert_eq!(
+        split_labels("127.0.0.1:7000@zone=a").unwrap(),
+        ("127.0.0.1:7000".to_string(), Some("a".to_string()))
+    );
+    assert_eq!(
+        split_labels("127.0.0.1:7000@17000@zone=b").unwrap(),
+        ("127.0.0.1:7000@17000".to_string(), Some("b".to_string()))
+    );
+    assert_eq!(
+        split_labels("[::1]:7000").unwrap(),
+        ("[::1]:7000".to_string(), None)
+    );
+    assert!(split_labels("127.0.0.1:7000@rack=1").is_err());
+    assert!(split_labels("127.0.0.1:7000@zone=").is_err());
+}
+
+#[test]
+fn test_parse_zones() {
+    let zones = parse_zones(
+        "\
+# node zone
+10.0.0.1:7000 a
+10.0.0.2      b
+
+",
+    )
+    .unwrap();
+    assert_eq!(zones.get("10.0.0.1:7000"), Some(&"a".to_string()));
+    assert_eq!(zones.get("10.0.0.2"), Some(&"b".to_string()));
+    assert!(parse_zones("10.0.0.1:7000").is_err());
+    assert!(parse_zones("10.0.0.1:7000 a b").is_err());
+}
+
+static ZONES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+pub fn load(path: &str) -> AsResult<()> {
+    let content = fs::read_to_string(path)?;
+    let zones = parse_zones(&content).map_err(Error::State)?;
+    let _ = ZONES.set(zones);
+    Ok(())
+}
+
+pub fn lookup(addr: &Addr) -> Option<String> {
+    let zones = ZONES.get()?;
+    zones
+        .get(&addr.to_string())
+        .or_else(|| zones.get(&addr.host))
+        .cloned()
+}
+
+pub fn split_labels(input: &str) -> AsResult<(String, Option<String>)> {
+    let mut zone = None;
+    let mut addr = Vec::new();
+    for part in input.split('@') {
+        if !part.contains('=') {
+            addr.push(part);
+            continue;
+        }
+        match part.split_once('=') {
+            Some(("zone", value)) if !value.is_empty() => zone = Some(value.to_string()),
+            _ => {
+                return Err(Error::State(format!(
+                    "bad label {} of {}, only zone=<name> is supported",
+                    part, input
+                )))
+            }
+        }
+    }
+    Ok((addr.join("@"), zone))
+}
+
+fn parse_zones(content: &str) -> Result<HashMap<String, String>, String> {
+    let mut zones = HashMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 2 {
+            return Err(format!(
+                "bad zone line '{}', must be formatted as '<node> <zone>'",
+                line
+            ));
+        }
+        zones.insert(fields[0].to_string(), fields[1].to_string());
+    }
+    Ok(zones)
+}